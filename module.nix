@@ -10,32 +10,53 @@ in
     listen = {
       sflow = {
         addr = lib.mkOption {
-          type = lib.types.str;
-          description = lib.mdDoc "The ip address the sflow listener should be listening on.";
-          default = "::";
+          type = lib.types.nullOr lib.types.str;
+          description = lib.mdDoc "The ip address the sflow listener should be listening on, `::` if only the port is set. Unless one of them is set, it is taken from `configFile`.";
+          default = null;
         };
         port = lib.mkOption {
-          type = lib.types.port;
-          description = lib.mdDoc "The port the sflow listener should be listening on.";
-          default = 6343;
+          type = lib.types.nullOr lib.types.port;
+          description = lib.mdDoc "The port the sflow listener should be listening on, 6343 if only the address is set. Unless one of them is set, it is taken from `configFile`.";
+          default = null;
         };
       };
       metrics = {
         addr = lib.mkOption {
-          type = lib.types.str;
-          description = lib.mdDoc "The ip address the metrics listener should be listening on.";
-          default = "::";
+          type = lib.types.nullOr lib.types.str;
+          description = lib.mdDoc "The ip address the metrics listener should be listening on, `::` if only the port is set. Unless one of them is set, it is taken from `configFile`.";
+          default = null;
         };
         port = lib.mkOption {
-          type = lib.types.port;
-          description = lib.mdDoc "The port the metrics listener should be listening on.";
-          default = 9144;
+          type = lib.types.nullOr lib.types.port;
+          description = lib.mdDoc "The port the metrics listener should be listening on, 9144 if only the address is set. Unless one of them is set, it is taken from `configFile`.";
+          default = null;
         };
       };
     };
+    ixf = lib.mkOption {
+      type = lib.types.nullOr lib.types.str;
+      description = lib.mdDoc "The path or http(s) url of an IX-F member export to import routers from.";
+      default = null;
+    };
+    strictAgentAddr = lib.mkOption {
+      type = lib.types.nullOr lib.types.bool;
+      description = lib.mdDoc "Drop sflow datagrams whose sender address differs from the agent address they claim, taken from `configFile` if null.";
+      default = null;
+    };
+    removeStaleSeries = lib.mkOption {
+      type = lib.types.nullOr lib.types.bool;
+      description = lib.mdDoc "Remove series of routers, agents, ports and ether types once they disappear from the meta configuration, taken from `configFile` if null.";
+      default = null;
+    };
+    configFile = lib.mkOption {
+      type = lib.types.nullOr lib.types.path;
+      description = lib.mdDoc "The runtime configuration file, the other options take precedence over it unless they are null.";
+      default = null;
+    };
     metaPath = lib.mkOption {
-      type = lib.types.str;
-      description = lib.mdDoc "The path where the meta configuration file is located.";
+      type = with lib.types; nullOr (either str (listOf str));
+      description = lib.mdDoc "The paths where the meta configuration files or folders are located, taken from `configFile` if null.";
+      default = null;
     };
   };
 
@@ -51,15 +72,30 @@ in
       environment =
         let
           addrToString = addr: port: "${if (lib.hasInfix ":" addr) then "[${addr}]" else addr}:${toString port}";
+          isSet = listen: listen.addr != null || listen.port != null;
+          listenAddr = listen: defaultPort: addrToString
+            (if listen.addr != null then listen.addr else "::")
+            (if listen.port != null then listen.port else defaultPort);
         in
-        {
-          SFLOW_EXPORTER_SFlOW_LISTEN_ADDR = addrToString cfg.listen.sflow.addr cfg.listen.sflow.port;
-          SFLOW_EXPORTER_METRICS_LISTEN_ADDR = addrToString cfg.listen.metrics.addr cfg.listen.metrics.port;
-          SFLOW_EXPORTER_META = cfg.metaPath;
+        lib.optionalAttrs (isSet cfg.listen.sflow) {
+          SFLOW_EXPORTER_SFlOW_LISTEN_ADDR = listenAddr cfg.listen.sflow 6343;
+        } // lib.optionalAttrs (isSet cfg.listen.metrics) {
+          SFLOW_EXPORTER_METRICS_LISTEN_ADDR = listenAddr cfg.listen.metrics 9144;
+        } // lib.optionalAttrs (cfg.metaPath != null) {
+          SFLOW_EXPORTER_META = lib.concatStringsSep "," (lib.toList cfg.metaPath);
+        } // lib.optionalAttrs (cfg.strictAgentAddr != null) {
+          SFLOW_EXPORTER_STRICT_AGENT_ADDR = lib.boolToString cfg.strictAgentAddr;
+        } // lib.optionalAttrs (cfg.removeStaleSeries != null) {
+          SFLOW_EXPORTER_REMOVE_STALE_SERIES = lib.boolToString cfg.removeStaleSeries;
+        } // lib.optionalAttrs (cfg.ixf != null) {
+          SFLOW_EXPORTER_IXF = cfg.ixf;
+        } // lib.optionalAttrs (cfg.configFile != null) {
+          SFLOW_EXPORTER_CONFIG = cfg.configFile;
         };
 
       serviceConfig = {
         ExecStart = "${lib.getExe cfg.package} listen";
+        ExecReload = "${pkgs.coreutils}/bin/kill -HUP $MAINPID";
         DynamicUser = true;
         User = "sflow_exporter";
       };
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::{from_fn_with_state, Next};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Json;
+use tokio::sync::mpsc;
+
+use crate::meta::api::{ApiChange, ApiMeta};
+use crate::meta::{AgentStorage, RouterStorage};
+use crate::reload::{request_reload, Reload};
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+/// Shared by the handlers of the runtime api.
+#[derive(Clone)]
+struct ApiState {
+  meta: ApiMeta,
+  meta_update_tx: mpsc::Sender<Reload>,
+}
+
+/// Routes of the runtime api, which manages routers and agents next to the meta files.
+pub(super) fn router(
+  token: &str,
+  meta: ApiMeta,
+  meta_update_tx: mpsc::Sender<Reload>,
+) -> axum::Router {
+  axum::Router::new()
+    .route("/api/routers", get(list_routers))
+    .route(
+      "/api/routers/{mac}",
+      get(get_router).put(put_router).delete(delete_router),
+    )
+    .route("/api/agents", get(list_agents))
+    .route(
+      "/api/agents/{source}",
+      get(get_agent).put(put_agent).delete(delete_agent),
+    )
+    .route_layer(from_fn_with_state(Arc::from(token), authenticate))
+    .with_state(ApiState {
+      meta,
+      meta_update_tx,
+    })
+}
+
+/// Requires the api token as bearer token.
+pub(super) async fn authenticate(
+  State(token): State<Arc<str>>,
+  request: Request,
+  next: Next,
+) -> ApiResult<Response> {
+  let bearer = request
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "));
+
+  match bearer {
+    Some(bearer) if constant_time_eq(bearer.as_bytes(), token.as_bytes()) => {
+      Ok(next.run(request).await)
+    }
+    _ => Err((StatusCode::UNAUTHORIZED, "Invalid api token\n".to_string())),
+  }
+}
+
+async fn list_routers(State(state): State<ApiState>) -> ApiResult<Json<Vec<RouterStorage>>> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  Ok(Json(storage.routers().to_vec()))
+}
+
+async fn get_router(
+  State(state): State<ApiState>,
+  Path(mac): Path<String>,
+) -> ApiResult<Json<RouterStorage>> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  match storage.router(&mac).map_err(bad_request)? {
+    Some(router) => Ok(Json(router.clone())),
+    None => Err(not_found("Router", &mac)),
+  }
+}
+
+async fn put_router(
+  State(state): State<ApiState>,
+  Path(mac): Path<String>,
+  Json(router): Json<RouterStorage>,
+) -> ApiResult<Json<RouterStorage>> {
+  let change = ApiChange::put_router(&mac, router.clone()).map_err(bad_request)?;
+  apply(&state, change).await?;
+  Ok(Json(router))
+}
+
+async fn delete_router(
+  State(state): State<ApiState>,
+  Path(mac): Path<String>,
+) -> ApiResult<StatusCode> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  if storage.router(&mac).map_err(bad_request)?.is_none() {
+    return Err(not_found("Router", &mac));
+  }
+
+  let change = ApiChange::delete_router(&mac).map_err(bad_request)?;
+  apply(&state, change).await?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_agents(State(state): State<ApiState>) -> ApiResult<Json<Vec<AgentStorage>>> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  Ok(Json(storage.agents().to_vec()))
+}
+
+async fn get_agent(
+  State(state): State<ApiState>,
+  Path(source): Path<String>,
+) -> ApiResult<Json<AgentStorage>> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  match storage.agent(&source).map_err(bad_request)? {
+    Some(agent) => Ok(Json(agent.clone())),
+    None => Err(not_found("Agent", &source)),
+  }
+}
+
+async fn put_agent(
+  State(state): State<ApiState>,
+  Path(source): Path<String>,
+  Json(agent): Json<AgentStorage>,
+) -> ApiResult<Json<AgentStorage>> {
+  let change = ApiChange::put_agent(&source, agent.clone()).map_err(bad_request)?;
+  apply(&state, change).await?;
+  Ok(Json(agent))
+}
+
+async fn delete_agent(
+  State(state): State<ApiState>,
+  Path(source): Path<String>,
+) -> ApiResult<StatusCode> {
+  let storage = state.meta.read().await.map_err(internal_error)?;
+  if storage.agent(&source).map_err(bad_request)?.is_none() {
+    return Err(not_found("Agent", &source));
+  }
+
+  let change = ApiChange::delete_agent(&source).map_err(bad_request)?;
+  apply(&state, change).await?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Hands the change over to the sflow processing, which validates and applies it together with
+/// the meta files.
+async fn apply(state: &ApiState, change: ApiChange) -> ApiResult<()> {
+  match request_reload(&state.meta_update_tx, Some(change)).await {
+    Some(Ok(())) => Ok(()),
+    Some(Err(err)) => Err((StatusCode::UNPROCESSABLE_ENTITY, format!("{}\n", err))),
+    None => Err((
+      StatusCode::SERVICE_UNAVAILABLE,
+      "sflow processing is not running\n".to_string(),
+    )),
+  }
+}
+
+fn bad_request(err: anyhow::Error) -> (StatusCode, String) {
+  (StatusCode::BAD_REQUEST, format!("{:#}\n", err))
+}
+
+fn internal_error(err: anyhow::Error) -> (StatusCode, String) {
+  (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}\n", err))
+}
+
+fn not_found(kind: &str, key: &str) -> (StatusCode, String) {
+  (
+    StatusCode::NOT_FOUND,
+    format!("{} {} is not managed by the runtime api\n", kind, key),
+  )
+}
+
+// compares without exiting early, to not leak the token through response times
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
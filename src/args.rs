@@ -3,11 +3,40 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 
+use crate::meta::format::MetaFormat;
+use crate::meta::ixf::IxfSource;
+use crate::metrics::cardinality::Preseed;
+use crate::metrics::frame_size::FrameSizeLabel;
+use crate::sflow::record::Accounting;
+
+// Settings are optional, so they only override the configuration file if passed. Defaults are
+// defined by `Config`.
 #[derive(Parser)]
 #[clap(version, about)]
 pub(super) struct Args {
-  #[clap(long, short, env = "SFLOW_EXPORTER_META", default_value = "meta.yaml")]
-  pub(super) meta: PathBuf,
+  /// Runtime configuration file in yaml, json or toml.
+  #[clap(long, short, env = "SFLOW_EXPORTER_CONFIG")]
+  pub(super) config: Option<PathBuf>,
+  /// Meta files or folders of meta files, which are merged. [default: meta.yaml]
+  #[clap(long, short, env = "SFLOW_EXPORTER_META", value_delimiter = ',')]
+  pub(super) meta: Vec<PathBuf>,
+  /// Format of the meta files, picked by their extension by default.
+  #[clap(long, env = "SFLOW_EXPORTER_META_FORMAT")]
+  pub(super) meta_format: Option<MetaFormat>,
+  /// Seconds between checks of the meta files for changes, that were not reported by the file
+  /// system. Set to 0 to disable. [default: 30]
+  #[clap(long, env = "SFLOW_EXPORTER_META_POLL_INTERVAL")]
+  pub(super) meta_poll_interval: Option<u64>,
+  /// Path or http(s) url of an IX-F member export to import routers from.
+  #[clap(long, env = "SFLOW_EXPORTER_IXF")]
+  pub(super) ixf: Option<IxfSource>,
+  /// Seconds between polls of an IX-F member export served over http(s). [default: 300]
+  #[clap(long, env = "SFLOW_EXPORTER_IXF_INTERVAL")]
+  pub(super) ixf_interval: Option<u64>,
+  /// Seconds until fetching an IX-F member export over http(s) times out. [default: 30]
+  #[clap(long, env = "SFLOW_EXPORTER_IXF_TIMEOUT")]
+  pub(super) ixf_timeout: Option<u64>,
+  /// Enables debug logging, which may also be enabled by the configuration file.
   #[clap(long, short, env = "SFLOW_EXPORTER_DEBUG", default_value = "false")]
   pub(super) debug: bool,
   #[clap(subcommand)]
@@ -16,20 +45,133 @@ pub(super) struct Args {
 
 #[derive(Subcommand)]
 pub(super) enum Action {
+  /// Validates the configuration and meta files.
   Check,
-  Listen {
-    #[clap(
-      long,
-      short,
-      env = "SFLOW_EXPORTER_SFlOW_LISTEN_ADDR",
-      default_value = "[::]:6343"
-    )]
-    sflow_addr: SocketAddr,
-    #[clap(
-      long,
-      env = "SFLOW_EXPORTER_METRICS_LISTEN_ADDR",
-      default_value = "[::]:9144"
-    )]
-    metrics_addr: SocketAddr,
-  },
+  /// Prints the JSON Schema of meta files.
+  Schema,
+  Listen(Box<ListenArgs>),
+  /// Listens for sflow datagrams and writes a meta file skeleton of the agents, routers and
+  /// ether types seen.
+  Discover(DiscoverArgs),
+}
+
+#[derive(clap::Args)]
+pub(super) struct DiscoverArgs {
+  /// [default: [::]:6343]
+  #[clap(long, short, env = "SFLOW_EXPORTER_SFlOW_LISTEN_ADDR")]
+  pub(super) sflow_addr: Option<SocketAddr>,
+  /// Seconds to listen for sflow datagrams.
+  #[clap(long, default_value = "60")]
+  pub(super) duration: u64,
+  /// Existing meta file to merge the discovered agents, routers and ether types into.
+  #[clap(long)]
+  pub(super) merge: Option<PathBuf>,
+  /// File to write the meta skeleton to, instead of stdout.
+  #[clap(long, short)]
+  pub(super) output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(super) struct ListenArgs {
+  /// [default: [::]:6343]
+  #[clap(long, short, env = "SFLOW_EXPORTER_SFlOW_LISTEN_ADDR")]
+  pub(super) sflow_addr: Option<SocketAddr>,
+  /// [default: [::]:9144]
+  #[clap(long, env = "SFLOW_EXPORTER_METRICS_LISTEN_ADDR")]
+  pub(super) metrics_addr: Option<SocketAddr>,
+  /// Drop datagrams whose UDP sender address differs from the agent address they claim.
+  #[clap(
+    long,
+    env = "SFLOW_EXPORTER_STRICT_AGENT_ADDR",
+    num_args = 0..=1,
+    require_equals = true,
+    default_missing_value = "true"
+  )]
+  pub(super) strict_agent_addr: Option<bool>,
+  /// Remove series of routers, agents, ports and ether types once they disappear from meta.
+  #[clap(
+    long,
+    env = "SFLOW_EXPORTER_REMOVE_STALE_SERIES",
+    num_args = 0..=1,
+    require_equals = true,
+    default_missing_value = "true"
+  )]
+  pub(super) remove_stale_series: Option<bool>,
+  /// Which series of sflow_router_bytes are created before any traffic is seen. [default: full]
+  #[clap(long, env = "SFLOW_EXPORTER_PRESEED")]
+  pub(super) preseed: Option<Preseed>,
+  /// Upper bound of sflow_router_bytes series, bytes of further series are counted as overflow.
+  #[clap(long, env = "SFLOW_EXPORTER_MAX_SERIES")]
+  pub(super) max_series: Option<usize>,
+  /// Bytes a router pair has to exchange before it gets its own series, until then it is
+  /// aggregated into the `other` router.
+  #[clap(long, env = "SFLOW_EXPORTER_RARE_PAIR_BYTES")]
+  pub(super) rare_pair_bytes: Option<u64>,
+  /// Octets of a frame accounted as bytes. [default: l2_fcs]
+  #[clap(long, env = "SFLOW_EXPORTER_ACCOUNTING")]
+  pub(super) accounting: Option<Accounting>,
+  /// Seconds without datagrams, after which an agent is considered down. [default: 60]
+  #[clap(long, env = "SFLOW_EXPORTER_AGENT_TIMEOUT")]
+  pub(super) agent_timeout: Option<u64>,
+  /// Labels of the frame size histogram, which is only exported if set.
+  #[clap(long, env = "SFLOW_EXPORTER_FRAME_SIZE_LABELS", value_delimiter = ',')]
+  pub(super) frame_size_labels: Option<Vec<FrameSizeLabel>>,
+  /// Upper bounds of the frame size histogram buckets in bytes.
+  /// [default: 64,128,256,512,576,1024,1518,2048,4096,9216]
+  #[clap(long, env = "SFLOW_EXPORTER_FRAME_SIZE_BUCKETS", value_delimiter = ',')]
+  pub(super) frame_size_buckets: Option<Vec<u64>>,
+  /// Replaces the `sflow` prefix of all metric names.
+  #[clap(long, env = "SFLOW_EXPORTER_NAMESPACE")]
+  pub(super) namespace: Option<String>,
+  /// New names of label names, e.g. `in=src,out=dst`.
+  #[clap(long, env = "SFLOW_EXPORTER_LABEL_NAMES", value_delimiter = ',', value_parser = parse_key_value)]
+  pub(super) label_names: Option<Vec<(String, String)>>,
+  /// Labels added to every series, e.g. `ixp=dd-ix,lan=primary`.
+  #[clap(long, env = "SFLOW_EXPORTER_CONST_LABELS", value_delimiter = ',', value_parser = parse_key_value)]
+  pub(super) const_labels: Option<Vec<(String, String)>>,
+  /// Offer the OpenMetrics format to scrapers, which exports counters with a `_total` suffix.
+  #[clap(
+    long,
+    env = "SFLOW_EXPORTER_OPENMETRICS",
+    num_args = 0..=1,
+    require_equals = true,
+    default_missing_value = "true"
+  )]
+  pub(super) openmetrics: Option<bool>,
+  /// Prometheus remote write url the metrics are pushed to, which is only enabled if set.
+  #[clap(long, env = "SFLOW_EXPORTER_REMOTE_WRITE_URL")]
+  pub(super) remote_write_url: Option<String>,
+  /// Seconds between pushes to the remote write url. [default: 30]
+  #[clap(long, env = "SFLOW_EXPORTER_REMOTE_WRITE_INTERVAL")]
+  pub(super) remote_write_interval: Option<u64>,
+  /// Username of the remote write url, authenticated with basic auth.
+  #[clap(long, env = "SFLOW_EXPORTER_REMOTE_WRITE_USERNAME")]
+  pub(super) remote_write_username: Option<String>,
+  #[clap(
+    long,
+    env = "SFLOW_EXPORTER_REMOTE_WRITE_PASSWORD",
+    hide_env_values = true
+  )]
+  pub(super) remote_write_password: Option<String>,
+  /// Bearer token of the remote write url.
+  #[clap(
+    long,
+    env = "SFLOW_EXPORTER_REMOTE_WRITE_BEARER_TOKEN",
+    hide_env_values = true
+  )]
+  pub(super) remote_write_bearer_token: Option<String>,
+  /// Bearer token of the runtime api, which is only enabled if set.
+  #[clap(long, env = "SFLOW_EXPORTER_API_TOKEN", hide_env_values = true)]
+  pub(super) api_token: Option<String>,
+  /// Meta file the routers and agents of the runtime api are persisted to, they are only kept
+  /// in memory by default.
+  #[clap(long, env = "SFLOW_EXPORTER_API_META_FILE")]
+  pub(super) api_meta_file: Option<PathBuf>,
+}
+
+fn parse_key_value(raw: &str) -> Result<(String, String), String> {
+  match raw.split_once('=') {
+    Some((key, value)) => Ok((key.to_string(), value.to_string())),
+    None => Err(format!("expected key=value, got {:?}", raw)),
+  }
 }
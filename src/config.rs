@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::args::{Action, Args};
+use crate::meta::format::MetaFormat;
+use crate::meta::ixf::{IxfSource, DEFAULT_IXF_TIMEOUT};
+use crate::metrics::cardinality::Preseed;
+use crate::metrics::exposition::{is_valid_name, MetricNaming, LABEL_NAMES};
+use crate::metrics::frame_size::{FrameSizeLabel, DEFAULT_FRAME_SIZE_BUCKETS};
+use crate::metrics::remote_write::{RemoteWriteAuth, RemoteWriteOptions};
+use crate::sflow::record::Accounting;
+
+// an inotify event carries a 16 byte header and a file name of up to 255 bytes plus nul
+const MIN_INOTIFY_BUFFER: usize = 16 + 255 + 1;
+
+/// Runtime configuration of the exporter. Command line arguments and environment variables
+/// take precedence over the configuration file.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Config {
+  pub(super) meta: Vec<PathBuf>,
+  pub(super) meta_format: Option<MetaFormat>,
+  pub(super) meta_poll_interval: u64,
+  pub(super) ixf: Option<IxfSource>,
+  pub(super) ixf_interval: u64,
+  /// Seconds until fetching the IX-F member export times out.
+  pub(super) ixf_timeout: u64,
+  pub(super) debug: bool,
+  pub(super) sflow_addr: SocketAddr,
+  pub(super) metrics_addr: SocketAddr,
+  pub(super) strict_agent_addr: bool,
+  pub(super) remove_stale_series: bool,
+  pub(super) preseed: Preseed,
+  pub(super) max_series: Option<usize>,
+  pub(super) rare_pair_bytes: Option<u64>,
+  pub(super) accounting: Accounting,
+  pub(super) agent_timeout: u64,
+  pub(super) frame_size_labels: Option<Vec<FrameSizeLabel>>,
+  pub(super) frame_size_buckets: Vec<u64>,
+  /// Replaces the `sflow` prefix of all metric names.
+  pub(super) namespace: Option<String>,
+  /// New names of label names, e.g. `in: src`.
+  pub(super) label_names: HashMap<String, String>,
+  /// Labels added to every series.
+  pub(super) const_labels: BTreeMap<String, String>,
+  pub(super) openmetrics: bool,
+  pub(super) remote_write_url: Option<String>,
+  pub(super) remote_write_interval: u64,
+  pub(super) remote_write_username: Option<String>,
+  pub(super) remote_write_password: Option<String>,
+  pub(super) remote_write_bearer_token: Option<String>,
+  /// Seconds until a push to the remote write url times out.
+  pub(super) remote_write_timeout: u64,
+  /// Retries of a failed push, before its samples are dropped.
+  pub(super) remote_write_max_retries: u32,
+  pub(super) api_token: Option<String>,
+  pub(super) api_meta_file: Option<PathBuf>,
+  /// Size of the kernel receive buffer of the sflow socket, the system default if unset.
+  pub(super) udp_receive_buffer: Option<usize>,
+  /// Reloads that may be queued while the sflow processor is busy.
+  pub(super) reload_queue: usize,
+  /// Bytes of inotify events read at once.
+  pub(super) inotify_buffer: usize,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      meta: vec![PathBuf::from("meta.yaml")],
+      meta_format: None,
+      meta_poll_interval: 30,
+      ixf: None,
+      ixf_interval: 300,
+      ixf_timeout: DEFAULT_IXF_TIMEOUT.as_secs(),
+      debug: false,
+      sflow_addr: "[::]:6343".parse().unwrap(),
+      metrics_addr: "[::]:9144".parse().unwrap(),
+      strict_agent_addr: false,
+      remove_stale_series: false,
+      preseed: Preseed::default(),
+      max_series: None,
+      rare_pair_bytes: None,
+      accounting: Accounting::default(),
+      agent_timeout: 60,
+      frame_size_labels: None,
+      frame_size_buckets: DEFAULT_FRAME_SIZE_BUCKETS.to_vec(),
+      namespace: None,
+      label_names: HashMap::new(),
+      const_labels: BTreeMap::new(),
+      openmetrics: false,
+      remote_write_url: None,
+      remote_write_interval: 30,
+      remote_write_username: None,
+      remote_write_password: None,
+      remote_write_bearer_token: None,
+      remote_write_timeout: 10,
+      remote_write_max_retries: 5,
+      api_token: None,
+      api_meta_file: None,
+      udp_receive_buffer: None,
+      reload_queue: 10,
+      inotify_buffer: 1024,
+    }
+  }
+}
+
+impl Config {
+  /// Reads the configuration file, if any, and layers the command line arguments on top.
+  pub(super) async fn load(args: &Args) -> anyhow::Result<Self> {
+    let mut config = match &args.config {
+      Some(path) => {
+        let raw_config = tokio::fs::read_to_string(path)
+          .await
+          .with_context(|| format!("Failed to read {}", path.display()))?;
+        MetaFormat::of(path)
+          .parse::<Config>(&raw_config)
+          .with_context(|| format!("Failed to parse {}", path.display()))?
+      }
+      None => Config::default(),
+    };
+
+    if !args.meta.is_empty() {
+      config.meta = args.meta.clone();
+    }
+    config.meta_format = args.meta_format.or(config.meta_format);
+    config.meta_poll_interval = args.meta_poll_interval.unwrap_or(config.meta_poll_interval);
+    config.ixf = args.ixf.clone().or(config.ixf);
+    config.ixf_interval = args.ixf_interval.unwrap_or(config.ixf_interval);
+    config.ixf_timeout = args.ixf_timeout.unwrap_or(config.ixf_timeout);
+    config.ixf = config
+      .ixf
+      .map(|ixf| ixf.with_timeout(Duration::from_secs(config.ixf_timeout)));
+    config.debug |= args.debug;
+
+    if let Action::Discover(discover) = &args.action {
+      config.sflow_addr = discover.sflow_addr.unwrap_or(config.sflow_addr);
+    }
+
+    if let Action::Listen(listen) = &args.action {
+      config.sflow_addr = listen.sflow_addr.unwrap_or(config.sflow_addr);
+      config.metrics_addr = listen.metrics_addr.unwrap_or(config.metrics_addr);
+      config.strict_agent_addr = listen.strict_agent_addr.unwrap_or(config.strict_agent_addr);
+      config.remove_stale_series = listen
+        .remove_stale_series
+        .unwrap_or(config.remove_stale_series);
+      config.preseed = listen.preseed.unwrap_or(config.preseed);
+      config.max_series = listen.max_series.or(config.max_series);
+      config.rare_pair_bytes = listen.rare_pair_bytes.or(config.rare_pair_bytes);
+      config.accounting = listen.accounting.unwrap_or(config.accounting);
+      config.agent_timeout = listen.agent_timeout.unwrap_or(config.agent_timeout);
+      config.frame_size_labels = listen
+        .frame_size_labels
+        .clone()
+        .or(config.frame_size_labels);
+      config.frame_size_buckets = listen
+        .frame_size_buckets
+        .clone()
+        .unwrap_or(config.frame_size_buckets);
+      config.namespace = listen.namespace.clone().or(config.namespace);
+      if let Some(label_names) = &listen.label_names {
+        config.label_names = label_names.iter().cloned().collect();
+      }
+      if let Some(const_labels) = &listen.const_labels {
+        config.const_labels = const_labels.iter().cloned().collect();
+      }
+      config.openmetrics = listen.openmetrics.unwrap_or(config.openmetrics);
+      config.remote_write_url = listen.remote_write_url.clone().or(config.remote_write_url);
+      config.remote_write_interval = listen
+        .remote_write_interval
+        .unwrap_or(config.remote_write_interval);
+      config.remote_write_username = listen
+        .remote_write_username
+        .clone()
+        .or(config.remote_write_username);
+      config.remote_write_password = listen
+        .remote_write_password
+        .clone()
+        .or(config.remote_write_password);
+      config.remote_write_bearer_token = listen
+        .remote_write_bearer_token
+        .clone()
+        .or(config.remote_write_bearer_token);
+      config.api_token = listen.api_token.clone().or(config.api_token);
+      config.api_meta_file = listen.api_meta_file.clone().or(config.api_meta_file);
+    }
+
+    config.validate()?;
+
+    Ok(config)
+  }
+
+  fn validate(&self) -> anyhow::Result<()> {
+    if self.meta.is_empty() {
+      anyhow::bail!("At least one meta file has to be configured");
+    }
+
+    if self.api_meta_file.is_some() && self.api_token.is_none() {
+      anyhow::bail!("api_meta_file requires the runtime api to be enabled with api_token");
+    }
+
+    if self.frame_size_buckets.is_empty()
+      || !self
+        .frame_size_buckets
+        .windows(2)
+        .all(|bounds| bounds[0] < bounds[1])
+    {
+      anyhow::bail!("frame_size_buckets have to be ascending and not empty");
+    }
+
+    if self.ixf_timeout == 0 {
+      anyhow::bail!("ixf_timeout has to be at least 1");
+    }
+
+    self.validate_naming()?;
+    self.remote_write()?;
+
+    if self.reload_queue == 0 {
+      anyhow::bail!("reload_queue has to be at least 1");
+    }
+
+    if self.inotify_buffer < MIN_INOTIFY_BUFFER {
+      anyhow::bail!("inotify_buffer has to be at least {}", MIN_INOTIFY_BUFFER);
+    }
+
+    Ok(())
+  }
+
+  fn validate_naming(&self) -> anyhow::Result<()> {
+    if let Some(namespace) = &self.namespace {
+      if !is_valid_name(namespace, false) {
+        anyhow::bail!("namespace {:?} is not a valid metric name", namespace);
+      }
+    }
+
+    for (label, name) in &self.label_names {
+      if !LABEL_NAMES.contains(&label.as_str()) {
+        anyhow::bail!(
+          "label_names: {:?} is none of the labels {}",
+          label,
+          LABEL_NAMES.join(", ")
+        );
+      }
+      if !is_valid_name(name, true) {
+        anyhow::bail!("label_names: {:?} is not a valid label name", name);
+      }
+    }
+
+    // a series must not carry a label twice, which is checked across all metrics for simplicity
+    let mut names = HashSet::from(["le"]);
+    for label in LABEL_NAMES {
+      let name = self.label_names.get(label).map_or(label, String::as_str);
+      if !names.insert(name) {
+        anyhow::bail!("label_names: {:?} is used more than once", name);
+      }
+    }
+
+    for name in self.const_labels.keys() {
+      if !is_valid_name(name, true) {
+        anyhow::bail!("const_labels: {:?} is not a valid label name", name);
+      }
+      if names.contains(name.as_str()) {
+        anyhow::bail!(
+          "const_labels: {:?} clashes with a label of the metrics",
+          name
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Options of the remote write push, `None` if it is disabled.
+  pub(super) fn remote_write(&self) -> anyhow::Result<Option<RemoteWriteOptions>> {
+    let has_auth = self.remote_write_username.is_some()
+      || self.remote_write_password.is_some()
+      || self.remote_write_bearer_token.is_some();
+
+    let url = match &self.remote_write_url {
+      Some(url) => url,
+      None if has_auth => anyhow::bail!("remote_write credentials require remote_write_url"),
+      None => return Ok(None),
+    };
+    let url =
+      reqwest::Url::parse(url).with_context(|| format!("Invalid remote_write_url {}", url))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+      anyhow::bail!("remote_write_url has to be an http(s) url");
+    }
+
+    if self.remote_write_interval == 0 {
+      anyhow::bail!("remote_write_interval has to be at least 1");
+    }
+
+    let auth = match (
+      &self.remote_write_username,
+      &self.remote_write_password,
+      &self.remote_write_bearer_token,
+    ) {
+      (None, None, None) => None,
+      (Some(username), password, None) => Some(RemoteWriteAuth::Basic {
+        username: username.clone(),
+        password: password.clone(),
+      }),
+      (None, Some(_), None) => {
+        anyhow::bail!("remote_write_password requires remote_write_username")
+      }
+      (None, None, Some(token)) => Some(RemoteWriteAuth::Bearer(token.clone())),
+      (_, _, Some(_)) => {
+        anyhow::bail!("remote_write_bearer_token and basic auth are mutually exclusive")
+      }
+    };
+
+    Ok(Some(RemoteWriteOptions {
+      url,
+      interval: Duration::from_secs(self.remote_write_interval),
+      timeout: Duration::from_secs(self.remote_write_timeout),
+      auth,
+      max_retries: self.remote_write_max_retries,
+    }))
+  }
+
+  pub(super) fn metric_naming(&self) -> MetricNaming {
+    MetricNaming {
+      namespace: self.namespace.clone(),
+      label_names: self.label_names.clone(),
+      const_labels: self.const_labels.clone(),
+    }
+  }
+}
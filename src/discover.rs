@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use binrw::{BinRead, Endian};
+use tokio::net::UdpSocket;
+use tokio::select;
+use tracing::{info, warn};
+
+use crate::args::DiscoverArgs;
+use crate::config::Config;
+use crate::meta::format::MetaFormat;
+use crate::meta::skeleton::render_skeleton;
+use crate::sflow::record::{FlowRecord, HeaderProtocol};
+use crate::sflow::sample::Sample;
+use crate::sflow::SflowDatagram;
+use crate::utils::{bind_udp, datagram_buffer};
+
+/// Agents, source macs and ether types seen on the wire, with the bytes they accounted for.
+#[derive(Default)]
+pub(crate) struct Discovered {
+  pub(crate) agents: HashMap<IpAddr, u64>,
+  pub(crate) routers: HashMap<[u8; 6], u64>,
+  pub(crate) ether_types: HashMap<u16, u64>,
+}
+
+/// Listens for sflow datagrams and writes a meta file skeleton of everything that was seen.
+pub(super) async fn discover(args: &DiscoverArgs, config: &Config) -> anyhow::Result<()> {
+  let socket = bind_udp(config.sflow_addr, config.udp_receive_buffer)?;
+  info!(
+    "Discovering traffic at {}/udp for {} seconds...",
+    config.sflow_addr, args.duration
+  );
+
+  let discovered = listen(socket, Duration::from_secs(args.duration)).await?;
+  info!(
+    "Discovered {} agents, {} macs and {} ether types",
+    discovered.agents.len(),
+    discovered.routers.len(),
+    discovered.ether_types.len()
+  );
+
+  // the format of the merged meta file is kept, otherwise the output file picks it
+  let format = args
+    .merge
+    .as_deref()
+    .or(args.output.as_deref())
+    .map(MetaFormat::of)
+    .unwrap_or(MetaFormat::Yaml);
+  let skeleton = render_skeleton(args.merge.as_deref(), format, args.duration, &discovered).await?;
+
+  match &args.output {
+    Some(output) => tokio::fs::write(output, skeleton).await?,
+    None => print!("{}", skeleton),
+  }
+
+  Ok(())
+}
+
+async fn listen(socket: UdpSocket, duration: Duration) -> anyhow::Result<Discovered> {
+  let mut buf = datagram_buffer();
+  let mut discovered = Discovered::default();
+
+  let deadline = tokio::time::sleep(duration);
+  tokio::pin!(deadline);
+
+  loop {
+    let read = select! {
+      _ = &mut deadline => return Ok(discovered),
+      result = socket.recv_from(buf.as_mut_slice()) => { result?.0 }
+    };
+
+    let mut cursor = Cursor::new(&buf[..read]);
+    let datagram = match SflowDatagram::read_options(&mut cursor, Endian::Big, ()) {
+      Ok(datagram) => datagram,
+      Err(err) => {
+        warn!("Skipping unparsable datagram: {}", err);
+        continue;
+      }
+    };
+
+    discovered.capture(datagram);
+  }
+}
+
+impl Discovered {
+  fn capture(&mut self, datagram: SflowDatagram) {
+    let agent_bytes = self
+      .agents
+      .entry(IpAddr::from(&datagram.agent_addr))
+      .or_default();
+
+    for sample in datagram.samples {
+      let flow = match sample {
+        Sample::Flow(flow) => flow,
+        _ => continue,
+      };
+
+      for record in flow.records {
+        let packet_header = match record {
+          FlowRecord::RawPacketHeader(header) => header,
+          _ => continue,
+        };
+
+        let ethernet_header = match packet_header.protocol_header {
+          HeaderProtocol::Ethernet(header) => header,
+          _ => continue,
+        };
+
+        let bytes = packet_header.frame_length as u64 * flow.sample_rate as u64;
+        *agent_bytes += bytes;
+        *self.routers.entry(ethernet_header.src).or_default() += bytes;
+        // vlan tags are stripped, like when the traffic is accounted to routers
+        let ether_type = ethernet_header.untag().ether_type;
+        *self.ether_types.entry(ether_type).or_default() += bytes;
+      }
+    }
+  }
+}
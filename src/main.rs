@@ -1,38 +1,59 @@
-use std::ffi::OsString;
+use std::borrow::Cow;
 use std::future::IntoFuture;
 use std::io::Cursor;
-use std::path::{Path, PathBuf};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::extract::State;
-use axum::http::StatusCode;
-use axum::routing::get;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::from_fn_with_state;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
 use binrw::{BinRead, Endian};
 use clap::Parser;
-use futures_util::stream::StreamExt;
-use inotify::{Inotify, WatchMask};
-use prometheus::{Registry, TextEncoder};
 use tokio::net::{TcpListener, UdpSocket};
 use tokio::select;
 use tokio::sync::mpsc;
-use tracing::error;
+use tower_http::compression::CompressionLayer;
+use tracing::{error, warn};
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
+use crate::api::authenticate;
 use crate::args::{Action, Args};
-use crate::meta::{Meta, DEFAULT_ETHER_TYPE};
+use crate::config::Config;
+use crate::meta::api::{ApiChange, ApiMeta};
+use crate::meta::{Meta, MetaSource, PortRole};
+use crate::metrics::cardinality::CardinalityOptions;
+use crate::metrics::exposition::Exposition;
+use crate::metrics::frame_size::FrameSizeOptions;
+use crate::metrics::pipeline::MetaLookup;
+use crate::metrics::remote_write::push_metrics;
 use crate::metrics::Metrics;
-use crate::sflow::record::{FlowRecord, HeaderProtocol};
+use crate::neighbors::{neighbors_endpoint, parse_binding, Neighbors};
+use crate::reload::{poll_files, poll_ixf, reload_endpoint, watch_files, watch_sighup, Reload};
+use crate::sflow::record::{Accounting, FlowRecord, HeaderProtocol};
 use crate::sflow::sample::Sample;
 use crate::sflow::SflowDatagram;
+use crate::utils::bind_udp;
 use crate::utils::datagram_buffer;
+use crate::utils::fmt_mac;
 use crate::utils::shutdown_signal;
+use crate::violations::{mtu_violations_endpoint, violations_endpoint, Violations};
 
+mod api;
 mod args;
-
+mod config;
+mod discover;
 mod meta;
 mod metrics;
+mod neighbors;
+mod reload;
 mod sflow;
 mod utils;
+mod violations;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -45,16 +66,44 @@ async fn main() -> anyhow::Result<()> {
 
   tracing::subscriber::set_global_default(subscriber)?;
 
-  let (sflow_addr, metrics_addr) = match args.action {
-    Action::Check => {
-      Meta::load(&args.meta).await?;
-      info!("Config successfully parsed");
-      return Ok(());
-    }
-    Action::Listen {
-      sflow_addr,
-      metrics_addr,
-    } => (sflow_addr, metrics_addr),
+  if let Action::Schema = args.action {
+    println!("{}", meta::schema()?);
+    return Ok(());
+  }
+
+  let config = Config::load(&args).await?;
+
+  let meta_source = MetaSource {
+    paths: config.meta.clone(),
+    ixf: config.ixf.clone(),
+    format: config.meta_format,
+    api: config
+      .api_token
+      .is_some()
+      .then(|| ApiMeta::new(config.api_meta_file.clone())),
+  };
+
+  if let Action::Discover(discover) = &args.action {
+    return discover::discover(discover, &config).await;
+  }
+
+  if let Action::Check = args.action {
+    Meta::load(&meta_source, None).await?;
+    info!("Config successfully parsed");
+    return Ok(());
+  }
+
+  let options = ProcessOptions {
+    strict_agent_addr: config.strict_agent_addr,
+    remove_stale_series: config.remove_stale_series,
+    accounting: config.accounting,
+    debug: config.debug,
+  };
+
+  let cardinality = CardinalityOptions {
+    preseed: config.preseed,
+    max_series: config.max_series,
+    rare_pair_bytes: config.rare_pair_bytes,
   };
 
   info!(concat!(
@@ -65,69 +114,110 @@ async fn main() -> anyhow::Result<()> {
     "..."
   ));
 
-  let (meta_update_tx, meta_update_rx) = mpsc::channel(10);
+  let (meta_update_tx, meta_update_rx) = mpsc::channel(config.reload_queue);
 
-  let (metrics, registry) = Metrics::new();
+  let frame_sizes = config
+    .frame_size_labels
+    .clone()
+    .map(|labels| FrameSizeOptions {
+      labels,
+      buckets: config.frame_size_buckets.clone(),
+    });
 
-  let socket = UdpSocket::bind(sflow_addr).await?;
-  info!("sflow listening at {}/udp...", sflow_addr);
+  let (metrics, registry) = Metrics::new(
+    cardinality,
+    frame_sizes,
+    Duration::from_secs(config.agent_timeout),
+  );
 
-  let listener = TcpListener::bind(metrics_addr).await?;
-  info!("metrics listening at http://{}/metrics...", metrics_addr);
+  let socket = bind_udp(config.sflow_addr, config.udp_receive_buffer)?;
+  info!("sflow listening at {}/udp...", config.sflow_addr);
 
-  let router = axum::Router::new()
-    .route("/metrics", get(metrics_endpoint))
-    .with_state(registry)
-    .into_make_service();
+  let listener = TcpListener::bind(config.metrics_addr).await?;
+  info!(
+    "metrics listening at http://{}/metrics...",
+    config.metrics_addr
+  );
 
-  let inotify = {
-    let meta = std::path::absolute(&args.meta)?;
-    let meta_name: OsString = match meta.file_name() {
-      Some(parent) => parent.into(),
-      None => {
-        anyhow::bail!("Failed to watch for meta file changes. Filename could not be deteminated.")
-      }
-    };
-    let meta_parent = match meta.parent() {
-      Some(parent) => parent.to_path_buf(),
-      None => anyhow::bail!(
-        "Failed to watch for meta file changes. Parent folder could not be deteminated."
-      ),
-    };
+  let exposition = Exposition::new(registry, config.metric_naming(), config.openmetrics);
 
-    tokio::spawn(async move {
-      let inotify = Inotify::init()?;
-      inotify
-        .watches()
-        .add(meta_parent, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
-      let mut buf = [0; 1024];
-      let mut stream = inotify.into_event_stream(&mut buf)?;
-
-      while let Some(event) = stream.next().await.transpose()? {
-        let name = match event.name {
-          Some(name) => name,
-          None => continue,
-        };
+  let remote_write = config.remote_write()?;
+  if let Some(remote_write) = &remote_write {
+    info!(
+      "pushing metrics to {} every {:?}...",
+      remote_write.url, remote_write.interval
+    );
+  }
 
-        if name != meta_name {
-          continue;
-        }
-        meta_update_tx.send(()).await.unwrap()
-      }
+  let violations = Violations::default();
+  let neighbors = Neighbors::default();
 
-      Ok::<(), anyhow::Error>(())
-    })
-  };
+  // reloads are as privileged as the runtime api, if it is enabled
+  let mut reload_route = post(reload_endpoint);
+  if let Some(token) = &config.api_token {
+    reload_route =
+      reload_route.route_layer(from_fn_with_state(Arc::from(token.as_str()), authenticate));
+  }
+
+  let mut router = axum::Router::new()
+    .route(
+      "/metrics",
+      get(metrics_endpoint).layer(CompressionLayer::new().gzip(true).zstd(true)),
+    )
+    .with_state(exposition.clone())
+    .route("/violations", get(violations_endpoint))
+    .route("/violations/mtu", get(mtu_violations_endpoint))
+    .with_state(violations.clone())
+    .route("/neighbors", get(neighbors_endpoint))
+    .with_state(neighbors.clone())
+    .route("/-/reload", reload_route)
+    .with_state(meta_update_tx.clone());
+
+  if let (Some(token), Some(api_meta)) = (&config.api_token, &meta_source.api) {
+    router = router.merge(api::router(token, api_meta.clone(), meta_update_tx.clone()));
+    info!(
+      "runtime api listening at http://{}/api...",
+      config.metrics_addr
+    );
+  }
+
+  let inotify = tokio::spawn(watch_files(
+    meta_source
+      .watched_paths()
+      .into_iter()
+      .map(|path| path.to_path_buf())
+      .collect(),
+    config.inotify_buffer,
+    meta_update_tx.clone(),
+  ));
+
+  let meta_poll = tokio::spawn(poll_files(
+    meta_source.clone(),
+    Duration::from_secs(config.meta_poll_interval),
+    meta_update_tx.clone(),
+  ));
+
+  let ixf_poll = tokio::spawn(poll_ixf(
+    meta_source.ixf.clone(),
+    Duration::from_secs(config.ixf_interval),
+    meta_update_tx.clone(),
+  ));
+
+  let sighup = tokio::spawn(watch_sighup(meta_update_tx));
+
+  let push = tokio::spawn(push_metrics(exposition, remote_write));
 
   let handle = tokio::spawn(process_sflow(
     socket,
     meta_update_rx,
-    args.meta,
+    meta_source,
     metrics,
-    args.debug,
+    violations,
+    neighbors,
+    options,
   ));
 
-  let axum = axum::serve(listener, router)
+  let axum = axum::serve(listener, router.into_make_service())
     .with_graceful_shutdown(shutdown_signal())
     .into_future();
 
@@ -135,49 +225,120 @@ async fn main() -> anyhow::Result<()> {
     result = axum => { result? }
     result = handle => { result?? }
     result = inotify => { result?? }
+    result = meta_poll => { result?? }
+    result = ixf_poll => { result?? }
+    result = sighup => { result?? }
+    result = push => { result?? }
   }
 
   Ok(())
 }
 
+struct ProcessOptions {
+  strict_agent_addr: bool,
+  remove_stale_series: bool,
+  accounting: Accounting,
+  debug: bool,
+}
+
 async fn process_sflow(
   socket: UdpSocket,
-  mut meta_update_rx: mpsc::Receiver<()>,
-  meta_path: PathBuf,
+  mut meta_update_rx: mpsc::Receiver<Reload>,
+  meta_source: MetaSource,
   metrics: Metrics,
-  debug: bool,
+  violations: Violations,
+  neighbors: Neighbors,
+  options: ProcessOptions,
 ) -> anyhow::Result<()> {
   let mut buf = datagram_buffer();
-  let mut meta = load_meta(&meta_path, &metrics).await?;
+  let mut meta = load_meta(&meta_source, None, &metrics).await?;
 
   loop {
-    let read = select! {
-      _ = meta_update_rx.recv() => {
-        match  load_meta(&meta_path, &metrics).await {
-          Ok(new_meta) => meta = new_meta,
-          Err(err) => error!("Unable to load meta configuration, continuing with running configuration: {:?}", err),
+    let (read, peer) = select! {
+      Some(reload) = meta_update_rx.recv() => {
+        match load_meta(&meta_source, reload.change.as_ref(), &metrics).await {
+          Ok(new_meta) => {
+            if options.remove_stale_series {
+              metrics.remove_stale(&new_meta);
+            }
+            meta = new_meta;
+            reload.respond(Ok(()));
+          }
+          Err(err) if reload.change.is_some() => {
+            warn!("Rejected change of the runtime api: {:#}", err);
+            reload.respond(Err(format!("{:#}", err)));
+          }
+          Err(err) => {
+            error!("Unable to load meta configuration, continuing with running configuration: {:?}", err);
+            reload.respond(Err(format!("{:#}", err)));
+          }
         };
         continue;
       }
-      result = socket.recv(buf.as_mut_slice()) => { result? }
+      result = socket.recv_from(buf.as_mut_slice()) => { result? }
     };
 
     let mut cursor = Cursor::new(&buf[..read]);
 
-    let datagram = SflowDatagram::read_options(&mut cursor, Endian::Big, ())?;
+    let decode_start = Instant::now();
+    let datagram = SflowDatagram::read_options(&mut cursor, Endian::Big, ());
+    metrics
+      .pipeline()
+      .capture_datagram(read, decode_start.elapsed(), datagram.is_ok());
+
+    let datagram = match datagram {
+      Ok(datagram) => datagram,
+      Err(err) => {
+        if options.debug {
+          info!("Dropping undecodable datagram from {}: {}", peer.ip(), err);
+        }
+        continue;
+      }
+    };
+
+    let agent_addr = IpAddr::from(&datagram.agent_addr);
+
+    // sockets bound to [::] report ipv4 senders as ipv4-mapped ipv6 addresses
+    if options.strict_agent_addr && peer.ip().to_canonical() != agent_addr {
+      if options.debug {
+        info!(
+          "Dropping datagram of agent {} received from {}",
+          agent_addr,
+          peer.ip()
+        );
+      }
+      continue;
+    }
 
-    let agent = match meta.lookup_agent(&datagram.agent_addr) {
+    let agent = match meta.lookup_agent(&agent_addr) {
       Some(agent) => agent,
-      None => continue,
+      None => {
+        metrics.pipeline().capture_meta_miss(MetaLookup::Agent);
+        continue;
+      }
     };
 
+    metrics.capture_agent_datagram(&agent.label);
+
     for sample in datagram.samples {
+      metrics.pipeline().capture_sample(sample.kind());
       let flow = match sample {
         Sample::Flow(flow) => flow,
         _ => continue,
       };
 
+      for record in &flow.records {
+        metrics
+          .pipeline()
+          .capture_flow_record(record.kind(), record.format());
+      }
+
       metrics.capture_pagent_drops(&agent.label, flow.drops);
+      metrics.capture_agent_sample_rate(
+        &agent.label,
+        &format!("{}:{}", flow.source_id_type, flow.source_id_idx),
+        flow.sample_rate,
+      );
 
       for record in flow.records {
         let packet_header = match record {
@@ -185,55 +346,164 @@ async fn process_sflow(
           _ => continue,
         };
 
-        let ethernet_header = match packet_header.protocol_header {
+        let ethernet_header = match &packet_header.protocol_header {
           HeaderProtocol::Ethernet(header) => header,
           _ => continue,
         };
 
+        let frame = ethernet_header.untag();
+
+        if let Some((addr, mac)) = parse_binding(&frame) {
+          let (ipv4, ipv6) = neighbors.learn(addr, mac, &agent.label);
+          metrics.capture_learned_neighbors(ipv4, ipv6);
+        }
+
         // first cast, then multiply to prevent overflow (panic!)
-        let bytes = packet_header.frame_length as u64 * flow.sample_rate as u64;
-        let ether_type = meta.fmt_ether_type(ethernet_header.ether_type);
+        let bytes = packet_header.accounted_length(options.accounting) * flow.sample_rate as u64;
+        // tagged frames are accounted to the ether type of their payload
+        let ether_type = meta.fmt_ether_type(frame.ether_type);
+
+        let in_port = agent.lookup_port(flow.input_if_idx);
+        let out_port = agent.lookup_port(flow.output_if_idx);
 
-        let src = meta.lookup_router(&ethernet_header.src);
-        let dst = meta.lookup_router(&ethernet_header.dst);
+        for port in [in_port, out_port] {
+          if port.is_none() {
+            metrics.pipeline().capture_meta_miss(MetaLookup::Port);
+          }
+        }
 
-        if debug {
+        let in_port_name = in_port
+          .map(|port| Cow::Borrowed(port.name.as_str()))
+          .unwrap_or_else(|| Cow::Owned(flow.input_if_idx.to_string()));
+        let lookup_router = |mac: &[u8; 6]| {
+          meta.lookup_router(mac).or_else(|| {
+            // routers known by their peering addresses are resolved through learned neighbors
+            meta
+              .has_addr_routers()
+              .then(|| neighbors.find_map(mac, |addr| meta.lookup_router_addr(addr)))
+              .flatten()
+          })
+        };
+        let src_router = lookup_router(&ethernet_header.src);
+
+        // routers are only expected to send through edge ports, all others carry transit traffic
+        if let Some(router) = src_router {
+          if agent.port_role(flow.input_if_idx) == PortRole::Edge
+            && router.is_misplaced(&agent.label, &in_port_name)
+          {
+            metrics.capture_router_mac_violation(&router.label, &agent.label, &in_port_name);
+            violations.record(
+              &router.label,
+              &ethernet_header.src,
+              &agent.label,
+              &in_port_name,
+            );
+          }
+        }
+
+        // a router assigned to the port identifies the member even if its mac is unknown or shared
+        let src = in_port.and_then(|port| port.router.as_ref()).or(src_router);
+        let dst = out_port
+          .and_then(|port| port.router.as_ref())
+          .or_else(|| lookup_router(&ethernet_header.dst));
+
+        for router in [src, dst] {
+          if router.is_none() {
+            metrics.pipeline().capture_meta_miss(MetaLookup::Router);
+          }
+        }
+
+        if options.debug {
           info!(
             "[{}] {} => {} iface: {: >7} => {: <7}, {: >5} bytes {}",
             agent.label,
             src
               .map(|r| format!("{: >17}", r.label))
-              .unwrap_or_else(|| ethernet_header
-                .src
-                .iter()
-                .map(|seg| format!("{:02x}", seg))
-                .collect::<Vec<String>>()
-                .join(":")),
+              .unwrap_or_else(|| fmt_mac(&ethernet_header.src)),
             dst
               .map(|r| format!("{: <17}", r.label))
-              .unwrap_or_else(|| ethernet_header
-                .dst
-                .iter()
-                .map(|seg| format!("{:02x}", seg))
-                .collect::<Vec<String>>()
-                .join(":")),
-            flow.input_if_idx,
-            flow.output_if_idx,
+              .unwrap_or_else(|| fmt_mac(&ethernet_header.dst)),
+            in_port_name,
+            out_port
+              .map(|port| port.name.clone())
+              .unwrap_or_else(|| flow.output_if_idx.to_string()),
             bytes,
             ether_type
           );
         }
 
+        // frames only enter the fabric once through an edge port, so they are checked there
+        if let (Some(src), PortRole::Edge) = (src, agent.port_role(flow.input_if_idx)) {
+          let size = packet_header
+            .frame_length
+            .saturating_sub(packet_header.stripped_octets)
+            .saturating_sub(frame.header_length);
+          if let Some(mtu) = meta.mtu(frame.vlan).filter(|mtu| size > *mtu) {
+            metrics.capture_router_mtu_violations(&src.label, flow.sample_rate as u64);
+            violations.record_mtu(
+              &src.label,
+              &agent.label,
+              &in_port_name,
+              frame.vlan,
+              size,
+              mtu,
+            );
+          }
+        }
+
+        if let Some(in_port) = in_port {
+          metrics.capture_port_bytes(&agent.label, &in_port.name, "in", bytes);
+        }
+
+        if let Some(out_port) = out_port {
+          metrics.capture_port_bytes(&agent.label, &out_port.name, "out", bytes);
+        }
+
+        if !meta.should_count(
+          agent,
+          flow.source_id_idx,
+          flow.input_if_idx,
+          flow.output_if_idx,
+        ) {
+          continue;
+        }
+
         if let (Some(src), Some(dst)) = (src, dst) {
-          metrics.capture_router_bytes(&src.label, &dst.label, ether_type, bytes);
+          metrics.capture_router_traffic(
+            &src.label,
+            &dst.label,
+            ether_type,
+            bytes,
+            flow.sample_rate as u64,
+          );
         }
+
+        metrics.capture_frame_size(
+          src.map(|router| router.label.as_str()),
+          dst.map(|router| router.label.as_str()),
+          ether_type,
+          packet_header.frame_length as u64,
+          flow.sample_rate as u64,
+        );
       }
     }
   }
 }
 
-async fn load_meta(meta_path: &Path, metrics: &Metrics) -> anyhow::Result<Meta> {
-  let meta = Meta::load(meta_path).await?;
+async fn load_meta(
+  meta_source: &MetaSource,
+  change: Option<&ApiChange>,
+  metrics: &Metrics,
+) -> anyhow::Result<Meta> {
+  let meta = match Meta::load(meta_source, change).await {
+    Ok(meta) => meta,
+    // rejected changes of the runtime api leave the meta configuration untouched
+    Err(err) if change.is_some() => return Err(err),
+    Err(err) => {
+      metrics.capture_meta_reload(None);
+      return Err(err);
+    }
+  };
 
   info!(
     "Loaded {} routers, {} agents and {} ether types",
@@ -242,27 +512,20 @@ async fn load_meta(meta_path: &Path, metrics: &Metrics) -> anyhow::Result<Meta>
     meta.important_ether_type_count()
   );
 
-  for agent in meta.get_agents() {
-    metrics.capture_pagent_drops(&agent.label, 0);
-  }
-
-  for router_in in meta.get_routers() {
-    for router_out in meta.get_routers() {
-      for ether_type in meta.get_ether_types() {
-        metrics.capture_router_bytes(&router_in.label, &router_out.label, ether_type, 0);
-      }
+  metrics.preseed(&meta);
 
-      metrics.capture_router_bytes(&router_in.label, &router_out.label, DEFAULT_ETHER_TYPE, 0);
-    }
-  }
+  metrics.capture_meta_reload(Some(meta.hash()));
 
   Ok(meta)
 }
 
-async fn metrics_endpoint(State(registry): State<Registry>) -> Result<String, StatusCode> {
-  let encoder = TextEncoder::new();
-  match encoder.encode_to_string(&registry.gather()) {
-    Ok(metrics) => Ok(metrics),
+async fn metrics_endpoint(
+  State(exposition): State<Exposition>,
+  headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+  let accept = headers.get(ACCEPT).and_then(|accept| accept.to_str().ok());
+  match exposition.encode(exposition.negotiate(accept)) {
+    Ok((content_type, metrics)) => Ok(([(CONTENT_TYPE, content_type)], metrics)),
     Err(err) => {
       error!("Error encoding metrics: {:?}", err);
       Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -0,0 +1,199 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::meta::format::MetaFormat;
+use crate::meta::mac::MacPattern;
+use crate::meta::{AgentStorage, MetaStorage, RouterStorage};
+
+/// Routers and agents managed through the runtime API. They are merged with the meta files like
+/// any other fragment, and either kept in memory or persisted to their own meta file.
+#[derive(Clone)]
+pub(crate) struct ApiMeta {
+  file: Option<PathBuf>,
+  memory: Arc<Mutex<ApiStorage>>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub(crate) struct ApiStorage {
+  #[serde(default)]
+  routers: Vec<RouterStorage>,
+  #[serde(default)]
+  agents: Vec<AgentStorage>,
+}
+
+/// A change requested through the runtime API, it is only committed if the resulting meta
+/// configuration is valid.
+pub(crate) struct ApiChange(Change);
+
+enum Change {
+  PutRouter(RouterStorage),
+  DeleteRouter(MacPattern),
+  PutAgent(AgentStorage),
+  DeleteAgent(IpNet),
+}
+
+impl ApiMeta {
+  pub(crate) fn new(file: Option<PathBuf>) -> Self {
+    Self {
+      file,
+      memory: Arc::default(),
+    }
+  }
+
+  pub(super) fn path(&self) -> Option<&Path> {
+    self.file.as_deref()
+  }
+
+  /// Name of the fragment in error messages.
+  pub(super) fn origin(&self) -> PathBuf {
+    self
+      .file
+      .clone()
+      .unwrap_or_else(|| PathBuf::from("runtime api"))
+  }
+
+  pub(crate) async fn read(&self) -> anyhow::Result<ApiStorage> {
+    let file = match &self.file {
+      Some(file) => file,
+      None => return Ok(self.memory.lock().unwrap().clone()),
+    };
+
+    let raw_meta = match tokio::fs::read_to_string(file).await {
+      Ok(raw_meta) => raw_meta,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(ApiStorage::default()),
+      Err(err) => {
+        return Err(err).with_context(|| format!("Failed to read {}", file.display()));
+      }
+    };
+
+    MetaFormat::of(file)
+      .parse(&raw_meta)
+      .with_context(|| format!("Failed to parse {}", file.display()))
+  }
+
+  pub(super) async fn write(&self, storage: ApiStorage) -> anyhow::Result<()> {
+    let file = match &self.file {
+      Some(file) => file,
+      None => {
+        *self.memory.lock().unwrap() = storage;
+        return Ok(());
+      }
+    };
+
+    let raw_meta = MetaFormat::of(file).serialize(&storage)?;
+
+    // replace the file atomically, so no reload ever sees it half written
+    let mut tmp = file.clone().into_os_string();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, raw_meta)
+      .await
+      .with_context(|| format!("Failed to write {}", file.display()))?;
+    tokio::fs::rename(&tmp, file)
+      .await
+      .with_context(|| format!("Failed to write {}", file.display()))?;
+
+    Ok(())
+  }
+}
+
+impl ApiStorage {
+  pub(crate) fn routers(&self) -> &[RouterStorage] {
+    &self.routers
+  }
+
+  pub(crate) fn agents(&self) -> &[AgentStorage] {
+    &self.agents
+  }
+
+  pub(crate) fn router(&self, mac: &str) -> anyhow::Result<Option<&RouterStorage>> {
+    let mac = Some(mac.parse::<MacPattern>()?);
+    Ok(
+      self
+        .routers
+        .iter()
+        .find(|router| router.mac_pattern().ok().flatten() == mac),
+    )
+  }
+
+  pub(crate) fn agent(&self, source: &str) -> anyhow::Result<Option<&AgentStorage>> {
+    let source = parse_source(source)?;
+    Ok(
+      self
+        .agents
+        .iter()
+        .find(|agent| agent.source.prefix() == source),
+    )
+  }
+
+  pub(super) fn apply(&mut self, change: &ApiChange) {
+    match &change.0 {
+      Change::PutRouter(router) => {
+        let mac = router.mac_pattern().ok().flatten();
+        self
+          .routers
+          .retain(|existing| existing.mac_pattern().ok().flatten() != mac);
+        self.routers.push(router.clone());
+      }
+      Change::DeleteRouter(mac) => self
+        .routers
+        .retain(|existing| existing.mac_pattern().ok().flatten().as_ref() != Some(mac)),
+      Change::PutAgent(agent) => {
+        let source = agent.source.prefix();
+        self
+          .agents
+          .retain(|existing| existing.source.prefix() != source);
+        self.agents.push(agent.clone());
+      }
+      Change::DeleteAgent(source) => self
+        .agents
+        .retain(|existing| existing.source.prefix() != *source),
+    }
+  }
+
+  pub(super) fn into_meta_storage(self) -> MetaStorage {
+    MetaStorage {
+      routers: self.routers,
+      agents: self.agents,
+      ..MetaStorage::default()
+    }
+  }
+}
+
+impl ApiChange {
+  /// Puts a router, whose mac address has to match the one it is addressed by.
+  pub(crate) fn put_router(mac: &str, router: RouterStorage) -> anyhow::Result<Self> {
+    if Some(mac.parse::<MacPattern>()?) != router.mac_pattern()? {
+      anyhow::bail!("Mac addr {} does not match the one of the router", mac);
+    }
+    Ok(ApiChange(Change::PutRouter(router)))
+  }
+
+  /// Puts an agent, whose source has to match the one it is addressed by.
+  pub(crate) fn put_agent(source: &str, agent: AgentStorage) -> anyhow::Result<Self> {
+    if parse_source(source)? != agent.source.prefix() {
+      anyhow::bail!("Source {} does not match the one of the agent", source);
+    }
+    Ok(ApiChange(Change::PutAgent(agent)))
+  }
+
+  pub(crate) fn delete_router(mac: &str) -> anyhow::Result<Self> {
+    Ok(ApiChange(Change::DeleteRouter(mac.parse()?)))
+  }
+
+  pub(crate) fn delete_agent(source: &str) -> anyhow::Result<Self> {
+    Ok(ApiChange(Change::DeleteAgent(parse_source(source)?)))
+  }
+}
+
+fn parse_source(source: &str) -> anyhow::Result<IpNet> {
+  match (source.parse::<IpNet>(), source.parse::<IpAddr>()) {
+    (Ok(prefix), _) => Ok(prefix.trunc()),
+    (_, Ok(addr)) => Ok(IpNet::from(addr)),
+    _ => anyhow::bail!("Invalid agent source: {}", source),
+  }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Well known ether types, see the
+/// [IEEE registry](https://standards-oui.ieee.org/ethertype/eth.txt).
+const REGISTRY: &[(u16, &str)] = &[
+  (0x0800, "IPv4"),
+  (0x0806, "ARP"),
+  (0x0842, "WoL"),
+  (0x22f3, "TRILL"),
+  (0x6003, "DECnet"),
+  (0x8035, "RARP"),
+  (0x809b, "AppleTalk"),
+  (0x80f3, "AARP"),
+  (0x8100, "802.1Q"),
+  (0x8137, "IPX"),
+  (0x86dd, "IPv6"),
+  (0x8808, "EthernetFlowControl"),
+  (0x8809, "SlowProtocols"),
+  (0x8847, "MPLS"),
+  (0x8848, "MPLS-Multicast"),
+  (0x8863, "PPPoE-Discovery"),
+  (0x8864, "PPPoE-Session"),
+  (0x888e, "EAPOL"),
+  (0x88a8, "802.1ad"),
+  (0x88cc, "LLDP"),
+  (0x88e5, "MACsec"),
+  (0x88e7, "PBB"),
+  (0x88f7, "PTP"),
+  (0x8902, "CFM"),
+  (0x8906, "FCoE"),
+  (0x8914, "FIP"),
+  (0x8915, "RoCE"),
+  (0x9000, "Loopback"),
+  (0x9100, "QinQ"),
+];
+
+/// Ether types are either listed by their registry name or id, or mapped from their id to
+/// custom labels.
+#[derive(JsonSchema)]
+#[schemars(untagged)]
+pub(super) enum EtherTypesStorage {
+  Map(HashMap<u16, EtherTypeStorage>),
+  List(Vec<EtherTypeEntryStorage>),
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub(super) struct EtherTypeStorage {
+  pub(super) label: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(super) enum EtherTypeEntryStorage {
+  Id(u16),
+  Name(String),
+  Custom { id: u16, label: String },
+}
+
+/// Label of ether types that are neither configured, nor exported by their registry name.
+#[derive(Deserialize, Serialize, JsonSchema, Default, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum UnknownEtherTypes {
+  /// Grouped as `other`.
+  #[default]
+  Other,
+  /// Exported by their registry name, if any. Others are still grouped as `other`.
+  Registry,
+}
+
+impl Default for EtherTypesStorage {
+  fn default() -> Self {
+    EtherTypesStorage::Map(HashMap::new())
+  }
+}
+
+// not derived, as untagged enums lose the ability of formats like json to parse numeric map keys
+impl<'de> Deserialize<'de> for EtherTypesStorage {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct EtherTypesVisitor;
+
+    impl<'de> Visitor<'de> for EtherTypesVisitor {
+      type Value = EtherTypesStorage;
+
+      fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of ether types or a map of ether type ids to labels")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        HashMap::deserialize(MapAccessDeserializer::new(map)).map(EtherTypesStorage::Map)
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        Vec::deserialize(SeqAccessDeserializer::new(seq)).map(EtherTypesStorage::List)
+      }
+    }
+
+    deserializer.deserialize_any(EtherTypesVisitor)
+  }
+}
+
+impl From<HashMap<u16, String>> for EtherTypesStorage {
+  fn from(ether_types: HashMap<u16, String>) -> Self {
+    EtherTypesStorage::Map(
+      ether_types
+        .into_iter()
+        .map(|(id, label)| (id, EtherTypeStorage { label }))
+        .collect(),
+    )
+  }
+}
+
+impl EtherTypesStorage {
+  /// Resolves all ether types to their id and label.
+  pub(super) fn resolve(self) -> anyhow::Result<HashMap<u16, String>> {
+    match self {
+      EtherTypesStorage::Map(ether_types) => Ok(
+        ether_types
+          .into_iter()
+          .map(|(id, ether_type)| (id, ether_type.label))
+          .collect(),
+      ),
+      EtherTypesStorage::List(entries) => entries
+        .into_iter()
+        .map(|entry| match entry {
+          EtherTypeEntryStorage::Id(id) => Ok((
+            id,
+            registry_name(id)
+              .map(|name| name.to_string())
+              .unwrap_or_else(|| format!("{:#06x}", id)),
+          )),
+          EtherTypeEntryStorage::Name(name) => match registry_id(&name) {
+            Some(id) => Ok((id, registry_name(id).unwrap().to_string())),
+            None => anyhow::bail!("Unknown ether type: {}", name),
+          },
+          EtherTypeEntryStorage::Custom { id, label } => Ok((id, label)),
+        })
+        .collect(),
+    }
+  }
+}
+
+pub(super) fn registry_name(id: u16) -> Option<&'static str> {
+  REGISTRY
+    .iter()
+    .find(|(registered, _)| *registered == id)
+    .map(|(_, name)| *name)
+}
+
+pub(super) fn is_registry_name(label: &str) -> bool {
+  REGISTRY.iter().any(|(_, name)| *name == label)
+}
+
+fn registry_id(name: &str) -> Option<u16> {
+  REGISTRY
+    .iter()
+    .find(|(_, registered)| registered.eq_ignore_ascii_case(name))
+    .map(|(id, _)| *id)
+}
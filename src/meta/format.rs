@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// File format of meta and configuration files.
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MetaFormat {
+  Yaml,
+  Json,
+  Toml,
+}
+
+impl MetaFormat {
+  /// Picks the format by the extension of the file, falling back to yaml.
+  pub(crate) fn of(path: &Path) -> Self {
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some("json") => MetaFormat::Json,
+      Some("toml") => MetaFormat::Toml,
+      _ => MetaFormat::Yaml,
+    }
+  }
+
+  pub(crate) fn parse<T: DeserializeOwned>(&self, raw: &str) -> anyhow::Result<T> {
+    Ok(match self {
+      MetaFormat::Yaml => serde_yaml_ng::from_str(raw)?,
+      MetaFormat::Json => serde_json::from_str(raw)?,
+      MetaFormat::Toml => toml::from_str(raw)?,
+    })
+  }
+
+  pub(crate) fn serialize<T: Serialize>(&self, value: &T) -> anyhow::Result<String> {
+    Ok(match self {
+      MetaFormat::Yaml => serde_yaml_ng::to_string(value)?,
+      MetaFormat::Json => serde_json::to_string_pretty(value)?,
+      MetaFormat::Toml => toml::to_string(value)?,
+    })
+  }
+}
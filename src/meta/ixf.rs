@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::meta::RouterStorage;
+
+/// Location of an [IX-F Member Export](https://github.com/euro-ix/json-schemas), either a
+/// local file or an http(s) url.
+#[derive(Clone, Deserialize)]
+#[serde(from = "String")]
+pub(crate) enum IxfSource {
+  File(PathBuf),
+  Url(IxfUrl),
+}
+
+/// Timeout of fetching the export, unless configured otherwise.
+pub(crate) const DEFAULT_IXF_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub(crate) struct IxfUrl {
+  url: String,
+  client: reqwest::Client,
+  timeout: Duration,
+  cache: Arc<Mutex<Option<CachedExport>>>,
+}
+
+struct CachedExport {
+  etag: Option<String>,
+  body: String,
+}
+
+/// Describes how members of the export are mapped to routers.
+#[derive(Deserialize, Serialize, JsonSchema, Default)]
+pub(super) struct IxfStorage {
+  #[serde(default)]
+  label: IxfLabel,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ixp: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  vlan: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Default)]
+#[serde(rename_all = "snake_case")]
+enum IxfLabel {
+  #[default]
+  Asn,
+  Name,
+}
+
+#[derive(Deserialize)]
+struct MemberExport {
+  member_list: Vec<Member>,
+}
+
+#[derive(Deserialize)]
+struct Member {
+  asnum: u32,
+  name: Option<String>,
+  #[serde(default)]
+  connection_list: Vec<Connection>,
+}
+
+#[derive(Deserialize)]
+struct Connection {
+  ixp_id: u64,
+  #[serde(default)]
+  vlan_list: Vec<Vlan>,
+}
+
+#[derive(Deserialize)]
+struct Vlan {
+  vlan_id: Option<u64>,
+  ipv4: Option<VlanAddress>,
+  ipv6: Option<VlanAddress>,
+}
+
+#[derive(Deserialize)]
+struct VlanAddress {
+  address: Option<IpAddr>,
+  #[serde(default)]
+  mac_addresses: Vec<String>,
+}
+
+impl FromStr for IxfSource {
+  type Err = Infallible;
+
+  fn from_str(source: &str) -> Result<Self, Self::Err> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+      Ok(IxfSource::Url(IxfUrl {
+        url: source.to_string(),
+        client: reqwest::Client::new(),
+        timeout: DEFAULT_IXF_TIMEOUT,
+        cache: Arc::default(),
+      }))
+    } else {
+      Ok(IxfSource::File(PathBuf::from(source)))
+    }
+  }
+}
+
+impl From<String> for IxfSource {
+  fn from(source: String) -> Self {
+    match source.parse() {
+      Ok(source) => source,
+      Err(infallible) => match infallible {},
+    }
+  }
+}
+
+impl IxfSource {
+  /// Limits how long fetching the export may take, so a stalled server does not block loading
+  /// the meta configuration.
+  pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+    if let IxfSource::Url(url) = &mut self {
+      url.timeout = timeout;
+    }
+    self
+  }
+
+  pub(crate) fn path(&self) -> Option<&Path> {
+    match self {
+      IxfSource::File(path) => Some(path),
+      IxfSource::Url(_) => None,
+    }
+  }
+
+  /// Fetches the export again, if it is served over http(s). Returns if the export has changed.
+  pub(crate) async fn refresh(&self) -> anyhow::Result<bool> {
+    match self {
+      IxfSource::File(_) => Ok(false),
+      IxfSource::Url(url) => url.refresh().await,
+    }
+  }
+
+  pub(super) async fn read(&self) -> anyhow::Result<String> {
+    match self {
+      IxfSource::File(path) => Ok(tokio::fs::read_to_string(path).await?),
+      IxfSource::Url(url) => {
+        if url.cache.lock().unwrap().is_none() {
+          url.refresh().await?;
+        }
+
+        match url.cache.lock().unwrap().as_ref() {
+          Some(cached) => Ok(cached.body.clone()),
+          None => anyhow::bail!("IX-F member export {} has not been fetched", url.url),
+        }
+      }
+    }
+  }
+}
+
+impl IxfUrl {
+  async fn refresh(&self) -> anyhow::Result<bool> {
+    let etag = self
+      .cache
+      .lock()
+      .unwrap()
+      .as_ref()
+      .and_then(|cached| cached.etag.clone());
+
+    let mut request = self.client.get(&self.url).timeout(self.timeout);
+    if let Some(etag) = etag {
+      request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+      return Ok(false);
+    }
+
+    let response = response.error_for_status()?;
+    let etag = response
+      .headers()
+      .get(ETAG)
+      .and_then(|etag| etag.to_str().ok())
+      .map(|etag| etag.to_string());
+    let body = response.text().await?;
+
+    let mut cache = self.cache.lock().unwrap();
+    // servers without etag support always answer with the full export
+    let changed = cache.as_ref().is_none_or(|cached| cached.body != body);
+    *cache = Some(CachedExport { etag, body });
+
+    Ok(changed)
+  }
+}
+
+pub(super) fn parse_routers(
+  export: &str,
+  storage: &IxfStorage,
+) -> anyhow::Result<Vec<RouterStorage>> {
+  let export = serde_json::from_str::<MemberExport>(export)?;
+  let mut routers = Vec::new();
+
+  for member in export.member_list {
+    let label = match (&storage.label, member.name) {
+      (IxfLabel::Name, Some(name)) => name,
+      _ => member.asnum.to_string(),
+    };
+
+    // the same mac address is usually listed for ipv4 and ipv6
+    let mut macs = HashSet::new();
+
+    let vlans = member
+      .connection_list
+      .into_iter()
+      .filter(|connection| storage.ixp.is_none_or(|ixp| ixp == connection.ixp_id))
+      .flat_map(|connection| connection.vlan_list)
+      .filter(|vlan| storage.vlan.is_none_or(|id| Some(id) == vlan.vlan_id));
+
+    for vlan in vlans {
+      for address in [vlan.ipv4, vlan.ipv6].into_iter().flatten() {
+        // without mac addresses the router is identified by its learned peering address
+        if address.mac_addresses.is_empty() {
+          if let Some(addr) = address.address {
+            routers.push(RouterStorage {
+              mac: None,
+              addrs: vec![addr],
+              label: label.clone(),
+              pinned: Vec::new(),
+            });
+          }
+        }
+
+        for mac in address.mac_addresses {
+          if !macs.insert(normalize_mac(&mac)) {
+            continue;
+          }
+
+          routers.push(RouterStorage {
+            mac: Some(mac),
+            addrs: Vec::new(),
+            label: label.clone(),
+            pinned: Vec::new(),
+          });
+        }
+      }
+    }
+  }
+
+  Ok(routers)
+}
+
+fn normalize_mac(mac: &str) -> String {
+  mac
+    .chars()
+    .filter(char::is_ascii_hexdigit)
+    .map(|char| char.to_ascii_lowercase())
+    .collect()
+}
@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+/// A mac address, or a prefix of one like an OUI, matching all addresses starting with it.
+///
+/// Accepted notations are colon (`00:1c:73:00:00:01`) or dash (`00-1c-73-00-00-01`) separated,
+/// Cisco dotted (`001c.7300.0001`) and bare hex (`001c73000001`). Prefixes end with a wildcard,
+/// e.g. `00:1c:73:*`, `001c.73*` or `001c73*`.
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+pub(super) struct MacPattern {
+  mac: [u8; 6],
+  // number of leading bytes that have to match
+  len: usize,
+}
+
+impl MacPattern {
+  pub(super) fn len(&self) -> usize {
+    self.len
+  }
+
+  /// The pattern of the given length a mac address would be matched by.
+  pub(super) fn of(mac: &[u8; 6], len: usize) -> Self {
+    let mut masked = [0; 6];
+    masked[..len].copy_from_slice(&mac[..len]);
+    Self { mac: masked, len }
+  }
+}
+
+impl FromStr for MacPattern {
+  type Err = anyhow::Error;
+
+  fn from_str(input: &str) -> Result<Self, Self::Err> {
+    let invalid = || anyhow::anyhow!("Invalid mac addr: {}", input);
+
+    let (digits, wildcard) = match input.strip_suffix('*') {
+      Some(prefix) => (prefix.trim_end_matches([':', '-', '.']), true),
+      None => (input, false),
+    };
+    // a wildcard right after the digits may split the last group, like in `00:1c:7*`
+    let is_last_split = wildcard && digits.len() + 1 == input.len();
+
+    let separator = [':', '-', '.']
+      .into_iter()
+      .find(|separator| digits.contains(*separator));
+    let groups = match separator {
+      Some(separator) => digits.split(separator).collect::<Vec<_>>(),
+      None if digits.is_empty() => Vec::new(),
+      None => vec![digits],
+    };
+
+    let mut nibbles = String::new();
+    let last = groups.len().saturating_sub(1);
+    for (i, group) in groups.into_iter().enumerate() {
+      let valid = match separator {
+        // the wildcard may split a dotted group
+        Some('.') => group.len() == 4 || (wildcard && i == last && (1..4).contains(&group.len())),
+        Some(_) => (1..=2).contains(&group.len()),
+        None => true,
+      };
+      if !valid || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+      }
+      // colon and dash separated bytes may omit their leading zero, unless split by the wildcard
+      if separator.is_some_and(|separator| separator != '.')
+        && group.len() == 1
+        && !(is_last_split && i == last)
+      {
+        nibbles.push('0');
+      }
+      nibbles.push_str(group);
+    }
+
+    let len = nibbles.len() / 2;
+    if !nibbles.len().is_multiple_of(2) || len == 0 || len > 6 || (!wildcard && len != 6) {
+      return Err(invalid());
+    }
+
+    let mut mac = [0; 6];
+    for (i, byte) in mac.iter_mut().take(len).enumerate() {
+      *byte = u8::from_str_radix(&nibbles[i * 2..i * 2 + 2], 16)?;
+    }
+
+    Ok(Self { mac, len })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MAC: [u8; 6] = [0x00, 0x1c, 0x73, 0x00, 0x00, 0x01];
+
+  #[test]
+  fn parses_every_notation() {
+    for input in [
+      "00:1c:73:00:00:01",
+      "00:1C:73:00:00:01",
+      "0:1c:73:0:0:1",
+      "00-1c-73-00-00-01",
+      "001c.7300.0001",
+      "001c73000001",
+    ] {
+      let pattern = input.parse::<MacPattern>().unwrap();
+      assert!(pattern == MacPattern::of(&MAC, 6), "{}", input);
+    }
+  }
+
+  #[test]
+  fn parses_prefixes() {
+    for (input, len) in [
+      ("00:1c:73:*", 3),
+      ("00:1c:73*", 3),
+      ("00-1c-73-*", 3),
+      ("001c.73*", 3),
+      ("001c.7300.*", 4),
+      ("001c73*", 3),
+      ("00*", 1),
+      ("0:1c:73:*", 3),
+      ("00:1c:73:00:00:01*", 6),
+    ] {
+      let pattern = input.parse::<MacPattern>().unwrap();
+      assert_eq!(pattern.len(), len, "{}", input);
+      assert!(pattern == MacPattern::of(&MAC, len), "{}", input);
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_input() {
+    for input in [
+      "",
+      "*",
+      "00:1c:73:00:00",
+      "00:1c:73:00:00:01:02",
+      "001c73000001ff",
+      "001c7300000",
+      "00:1c-73:00:00:01",
+      "001c.7300:0001",
+      "001c.73.00.00.01",
+      "001:c7:30:00:00:1",
+      "00:1c:73:00:00:0g",
+      "001c.7*00",
+      "001c7*",
+      "00:1c:7*",
+      "00-1c-7*",
+    ] {
+      assert!(input.parse::<MacPattern>().is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn prefixes_match_addresses_starting_with_them() {
+    let oui = "00:1c:73:*".parse::<MacPattern>().unwrap();
+
+    assert!(oui == MacPattern::of(&MAC, oui.len()));
+    assert!(oui == MacPattern::of(&[0x00, 0x1c, 0x73, 0xff, 0xff, 0xff], oui.len()));
+    assert!(oui != MacPattern::of(&[0x00, 0x1c, 0x74, 0x00, 0x00, 0x01], oui.len()));
+    assert!(oui != MacPattern::of(&MAC, 6));
+  }
+}
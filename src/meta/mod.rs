@@ -0,0 +1,676 @@
+use std::collections::hash_map::Values;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::meta::api::{ApiChange, ApiMeta};
+use crate::meta::ether_type::{
+  is_registry_name, registry_name, EtherTypesStorage, UnknownEtherTypes,
+};
+use crate::meta::format::MetaFormat;
+use crate::meta::ixf::{parse_routers, IxfSource, IxfStorage};
+use crate::meta::mac::MacPattern;
+
+pub(crate) mod api;
+mod ether_type;
+pub(crate) mod format;
+pub(crate) mod ixf;
+mod mac;
+pub(crate) mod skeleton;
+
+pub(super) const DEFAULT_ETHER_TYPE: &str = "other";
+
+pub(super) struct Meta {
+  routers: HashMap<MacPattern, Router>,
+  // routers identified by their peering addresses, resolved through learned neighbors
+  addr_routers: HashMap<IpAddr, Router>,
+  router_count: usize,
+  // lengths of all router mac patterns, longest first
+  router_pattern_lens: Vec<usize>,
+  // sorted by prefix length, most specific first
+  agents: Vec<(IpNet, Agent)>,
+  ether_types: HashMap<u16, String>,
+  unknown_ether_types: UnknownEtherTypes,
+  counting: CountingPolicy,
+  mtu: Option<MtuStorage>,
+  hash: String,
+}
+
+/// Files and exports the meta configuration is assembled from.
+#[derive(Clone)]
+pub(super) struct MetaSource {
+  pub(super) paths: Vec<PathBuf>,
+  pub(super) ixf: Option<IxfSource>,
+  /// Overrides the format picked by the extension of each meta file.
+  pub(super) format: Option<MetaFormat>,
+  /// Routers and agents managed through the runtime API, if enabled.
+  pub(super) api: Option<ApiMeta>,
+}
+
+#[derive(Clone)]
+pub(super) struct Router {
+  pub(super) label: String,
+  // the router may only appear on these locations, if any
+  pinned: Vec<Location>,
+}
+
+#[derive(Clone)]
+struct Location {
+  agent: String,
+  port: Option<String>,
+}
+
+pub(super) struct Agent {
+  pub(super) label: String,
+  ports: HashMap<u32, Port>,
+}
+
+pub(super) struct Port {
+  pub(super) name: String,
+  pub(super) router: Option<Router>,
+  pub(super) role: PortRole,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Default, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum PortRole {
+  /// Port facing a router, every packet enters and leaves the fabric through exactly one of them.
+  #[default]
+  Edge,
+  /// Port interconnecting agents, packets seen here are also seen on an edge port.
+  Core,
+}
+
+/// Decides which samples are accounted to `sflow_router_bytes`.
+#[derive(Deserialize, Serialize, JsonSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum CountingPolicy {
+  /// Every sample is counted.
+  #[default]
+  All,
+  /// Only samples that entered the agent through an edge port are counted.
+  EdgeIngress,
+  /// Only samples that left the agent through an edge port are counted.
+  EdgeEgress,
+}
+
+/// Meta configuration of sflow_exporter.
+#[derive(Deserialize, JsonSchema, Default)]
+struct MetaStorage {
+  #[serde(default)]
+  routers: Vec<RouterStorage>,
+  #[serde(default)]
+  agents: Vec<AgentStorage>,
+  #[serde(default)]
+  ether_types: EtherTypesStorage,
+  unknown_ether_types: Option<UnknownEtherTypes>,
+  counting: Option<CountingPolicy>,
+  mtu: Option<MtuStorage>,
+  ixf: Option<IxfStorage>,
+}
+
+/// MTU of the peering LAN, frames carrying larger packets through edge ports are violations.
+#[derive(Deserialize, Serialize, JsonSchema)]
+struct MtuStorage {
+  /// MTU of untagged frames and vlans without their own MTU.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  default: Option<u32>,
+  /// MTU by vlan id.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  vlans: BTreeMap<u16, u32>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub(crate) struct RouterStorage {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  mac: Option<String>,
+  /// Peering addresses, which identify the router through learned ARP and neighbor discovery
+  /// bindings.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  addrs: Vec<IpAddr>,
+  label: String,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pinned: Vec<LocationStorage>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+struct LocationStorage {
+  agent: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  port: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub(crate) struct AgentStorage {
+  label: String,
+  source: AgentSourceStorage,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  ports: BTreeMap<u32, PortStorage>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(untagged)]
+enum AgentSourceStorage {
+  Prefix(#[schemars(with = "String")] IpNet),
+  Addr(IpAddr),
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+struct PortStorage {
+  name: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  router: Option<String>,
+  #[serde(default)]
+  role: PortRole,
+}
+
+impl Meta {
+  /// Loads the meta configuration. A change of the runtime API is only committed, if the
+  /// resulting configuration is valid.
+  pub(super) async fn load(
+    source: &MetaSource,
+    change: Option<&ApiChange>,
+  ) -> anyhow::Result<Self> {
+    let mut hasher = Sha256::new();
+    let mut fragments = Vec::new();
+    for file in source.meta_files().await? {
+      let raw_meta = tokio::fs::read_to_string(&file)
+        .await
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+      hasher.update(&raw_meta);
+      let format = source.format.unwrap_or_else(|| MetaFormat::of(&file));
+      let fragment = format
+        .parse::<MetaStorage>(&raw_meta)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+      fragments.push((file, fragment));
+    }
+
+    let api_storage = match (&source.api, change) {
+      (Some(api), change) => {
+        let mut storage = api.read().await?;
+        if let Some(change) = change {
+          storage.apply(change);
+        }
+        hasher.update(serde_json::to_string(&storage)?);
+        fragments.push((api.origin(), storage.clone().into_meta_storage()));
+        Some(storage)
+      }
+      (None, Some(_)) => anyhow::bail!("The runtime api is not enabled"),
+      (None, None) => None,
+    };
+
+    let meta = MetaStorage::merge(fragments)?;
+
+    let ixf_routers = match &source.ixf {
+      Some(ixf) => {
+        let export = ixf.read().await?;
+        hasher.update(&export);
+        parse_routers(&export, &meta.ixf.unwrap_or_default())?
+      }
+      None => Vec::new(),
+    };
+
+    // routers from the meta files take precedence over the ones from the member export
+    let mut routers = HashMap::new();
+    let mut addr_routers = HashMap::new();
+    // routers without a mac are only counted once, if they announce an addr of their own
+    let mut addr_only_count = 0;
+    for customer in ixf_routers.into_iter().chain(meta.routers) {
+      let mac = customer.mac_pattern()?;
+      if mac.is_none() && customer.addrs.is_empty() {
+        anyhow::bail!("Router {} has neither a mac nor an addr", customer.label);
+      }
+      if mac.is_none()
+        && customer
+          .addrs
+          .iter()
+          .any(|addr| !addr_routers.contains_key(addr))
+      {
+        addr_only_count += 1;
+      }
+
+      let router = Router {
+        label: customer.label,
+        pinned: customer
+          .pinned
+          .into_iter()
+          .map(|location| Location {
+            agent: location.agent,
+            port: location.port,
+          })
+          .collect(),
+      };
+
+      for addr in customer.addrs {
+        addr_routers.insert(addr, router.clone());
+      }
+      if let Some(mac) = mac {
+        routers.insert(mac, router);
+      }
+    }
+
+    let mut router_pattern_lens = routers
+      .keys()
+      .map(|pattern| pattern.len())
+      .collect::<Vec<_>>();
+    router_pattern_lens.sort_unstable_by(|a, b| b.cmp(a));
+    router_pattern_lens.dedup();
+
+    let mut agents = meta
+      .agents
+      .into_iter()
+      .map(|agent| {
+        let source = agent.source.prefix();
+        let ports = agent
+          .ports
+          .into_iter()
+          .map(|(if_idx, port)| {
+            (
+              if_idx,
+              Port {
+                name: port.name,
+                router: port.router.map(|label| Router {
+                  label,
+                  pinned: Vec::new(),
+                }),
+                role: port.role,
+              },
+            )
+          })
+          .collect();
+
+        (
+          source,
+          Agent {
+            label: agent.label,
+            ports,
+          },
+        )
+      })
+      .collect::<Vec<_>>();
+    agents.sort_by_key(|(source, _)| std::cmp::Reverse(source.prefix_len()));
+
+    let meta = Self {
+      router_count: routers.len() + addr_only_count,
+      routers,
+      addr_routers,
+      router_pattern_lens,
+      agents,
+      ether_types: meta.ether_types.resolve()?,
+      unknown_ether_types: meta.unknown_ether_types.unwrap_or_default(),
+      counting: meta.counting.unwrap_or_default(),
+      mtu: meta.mtu,
+      hash: format!("{:x}", hasher.finalize()),
+    };
+
+    if let (Some(api), Some(storage), Some(_)) = (&source.api, api_storage, change) {
+      api.write(storage).await?;
+    }
+
+    Ok(meta)
+  }
+
+  /// Sha256 of all files and exports the configuration was loaded from.
+  pub(super) fn hash(&self) -> &str {
+    &self.hash
+  }
+
+  pub(super) fn router_count(&self) -> usize {
+    self.router_count
+  }
+
+  pub(super) fn agent_count(&self) -> usize {
+    self.agents.len()
+  }
+
+  pub(super) fn important_ether_type_count(&self) -> usize {
+    self.ether_types.len()
+  }
+
+  pub(super) fn get_agents(&self) -> impl Iterator<Item = &Agent> {
+    self.agents.iter().map(|(_, agent)| agent)
+  }
+
+  pub(super) fn get_routers(&self) -> impl Iterator<Item = &Router> {
+    self.routers.values().chain(self.addr_routers.values())
+  }
+
+  pub(super) fn get_ether_types(&self) -> Values<'_, u16, String> {
+    self.ether_types.values()
+  }
+
+  /// Labels of all routers, including the ones only assigned to ports.
+  pub(super) fn router_labels(&self) -> HashSet<&str> {
+    let port_routers = self
+      .get_agents()
+      .flat_map(|agent| agent.get_ports())
+      .filter_map(|port| port.router.as_ref());
+
+    self
+      .get_routers()
+      .chain(port_routers)
+      .map(|router| router.label.as_str())
+      .collect()
+  }
+
+  /// Checks if samples may be exported with the given ether type label.
+  pub(super) fn is_ether_type_label(&self, label: &str) -> bool {
+    label == DEFAULT_ETHER_TYPE
+      || self.get_ether_types().any(|configured| configured == label)
+      || (self.unknown_ether_types == UnknownEtherTypes::Registry && is_registry_name(label))
+  }
+
+  /// Labels of all configured ether types, including the default one.
+  pub(super) fn ether_type_labels(&self) -> HashSet<&str> {
+    self
+      .get_ether_types()
+      .map(|label| label.as_str())
+      .chain([DEFAULT_ETHER_TYPE])
+      .collect()
+  }
+
+  /// Finds the router of a mac address, the longest matching mac pattern wins.
+  pub(super) fn lookup_router(&self, mac: &[u8; 6]) -> Option<&Router> {
+    self
+      .router_pattern_lens
+      .iter()
+      .find_map(|len| self.routers.get(&MacPattern::of(mac, *len)))
+  }
+
+  /// Finds the router of a learned peering address.
+  pub(super) fn lookup_router_addr(&self, addr: &IpAddr) -> Option<&Router> {
+    self.addr_routers.get(addr)
+  }
+
+  /// Checks if any router is identified by its peering addresses.
+  pub(super) fn has_addr_routers(&self) -> bool {
+    !self.addr_routers.is_empty()
+  }
+
+  pub(super) fn lookup_agent(&self, addr: &IpAddr) -> Option<&Agent> {
+    self
+      .agents
+      .iter()
+      .find(|(source, _)| source.contains(addr))
+      .map(|(_, agent)| agent)
+  }
+
+  /// Checks if a sample should be counted with respect to the configured counting policy, to
+  /// prevent packets sampled by multiple agents from being counted more than once.
+  /// Decides if a flow sample is counted, `source_idx` is the ifIndex of the port it was sampled
+  /// at. Agents sampling both directions report a packet once at its ingress and once at its
+  /// egress port, so only the sample taken in the counted direction is kept.
+  pub(super) fn should_count(
+    &self,
+    agent: &Agent,
+    source_idx: u32,
+    input_if_idx: u32,
+    output_if_idx: u32,
+  ) -> bool {
+    match self.counting {
+      CountingPolicy::All => true,
+      CountingPolicy::EdgeIngress => {
+        source_idx == input_if_idx && agent.port_role(input_if_idx) == PortRole::Edge
+      }
+      CountingPolicy::EdgeEgress => {
+        source_idx == output_if_idx && agent.port_role(output_if_idx) == PortRole::Edge
+      }
+    }
+  }
+
+  /// MTU of a vlan, or of untagged frames, if one is configured.
+  pub(super) fn mtu(&self, vlan: Option<u16>) -> Option<u32> {
+    let mtu = self.mtu.as_ref()?;
+    vlan
+      .and_then(|vlan| mtu.vlans.get(&vlan).copied())
+      .or(mtu.default)
+  }
+
+  pub(super) fn fmt_ether_type(&self, ether_type: u16) -> &str {
+    self
+      .ether_types
+      .get(&ether_type)
+      .map(|value| value.as_str())
+      .or_else(|| match self.unknown_ether_types {
+        UnknownEtherTypes::Other => None,
+        UnknownEtherTypes::Registry => registry_name(ether_type),
+      })
+      .unwrap_or(DEFAULT_ETHER_TYPE)
+  }
+}
+
+impl MetaStorage {
+  /// Merges the fragments of all meta files. Routers, agents, ether types and settings may only
+  /// be defined by one of the files.
+  fn merge(fragments: Vec<(PathBuf, MetaStorage)>) -> anyhow::Result<Self> {
+    let mut merged = MetaStorage::default();
+    let mut routers = HashMap::new();
+    let mut router_addrs = HashMap::new();
+    let mut agents = HashMap::new();
+    let mut ether_types = HashMap::new();
+    let mut resolved_ether_types = HashMap::new();
+    let mut settings = HashMap::new();
+
+    for (file, fragment) in fragments {
+      for router in &fragment.routers {
+        let mac = router
+          .mac_pattern()
+          .with_context(|| format!("Failed to parse {}", file.display()))?;
+        if let Some(mac) = mac {
+          check_conflict(&mut routers, mac, &file, || {
+            format!("Router {}", router.mac.as_deref().unwrap_or_default())
+          })?;
+        }
+        for addr in &router.addrs {
+          check_conflict(&mut router_addrs, *addr, &file, || {
+            format!("Router addr {}", addr)
+          })?;
+        }
+      }
+
+      for agent in &fragment.agents {
+        check_conflict(&mut agents, agent.source.prefix(), &file, || {
+          format!("Agent {}", agent.source.prefix())
+        })?;
+      }
+
+      let fragment_ether_types = fragment
+        .ether_types
+        .resolve()
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+      for id in fragment_ether_types.keys() {
+        check_conflict(&mut ether_types, *id, &file, || {
+          format!("Ether type {:#06x}", id)
+        })?;
+      }
+      resolved_ether_types.extend(fragment_ether_types);
+
+      if fragment.unknown_ether_types.is_some() {
+        check_conflict(&mut settings, "unknown_ether_types", &file, || {
+          "unknown_ether_types".to_string()
+        })?;
+        merged.unknown_ether_types = fragment.unknown_ether_types;
+      }
+
+      if fragment.counting.is_some() {
+        check_conflict(&mut settings, "counting", &file, || "counting".to_string())?;
+        merged.counting = fragment.counting;
+      }
+
+      if fragment.mtu.is_some() {
+        check_conflict(&mut settings, "mtu", &file, || "mtu".to_string())?;
+        merged.mtu = fragment.mtu;
+      }
+
+      if fragment.ixf.is_some() {
+        check_conflict(&mut settings, "ixf", &file, || "ixf".to_string())?;
+        merged.ixf = fragment.ixf;
+      }
+
+      merged.routers.extend(fragment.routers);
+      merged.agents.extend(fragment.agents);
+    }
+    merged.ether_types = resolved_ether_types.into();
+
+    Ok(merged)
+  }
+}
+
+impl RouterStorage {
+  fn mac_pattern(&self) -> anyhow::Result<Option<MacPattern>> {
+    self.mac.as_deref().map(MacPattern::from_str).transpose()
+  }
+}
+
+impl AgentSourceStorage {
+  fn prefix(&self) -> IpNet {
+    match self {
+      AgentSourceStorage::Prefix(prefix) => prefix.trunc(),
+      AgentSourceStorage::Addr(addr) => IpNet::from(*addr),
+    }
+  }
+}
+
+impl MetaSource {
+  /// Local files and folders that should be watched for changes.
+  pub(super) fn watched_paths(&self) -> Vec<&Path> {
+    let mut paths = self.paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+    if let Some(path) = self.ixf.as_ref().and_then(IxfSource::path) {
+      paths.push(path);
+    }
+    if let Some(path) = self.api.as_ref().and_then(ApiMeta::path) {
+      paths.push(path);
+    }
+    paths
+  }
+
+  /// Sha256 of all local files the configuration is loaded from, to detect changes without
+  /// relying on file system events.
+  pub(super) async fn hash_files(&self) -> anyhow::Result<String> {
+    let mut files = self.meta_files().await?;
+    if let Some(path) = self.ixf.as_ref().and_then(IxfSource::path) {
+      files.push(path.to_path_buf());
+    }
+    // the file of the runtime api is only created on its first change
+    if let Some(path) = self.api.as_ref().and_then(ApiMeta::path) {
+      if tokio::fs::try_exists(path).await? {
+        files.push(path.to_path_buf());
+      }
+    }
+
+    let mut hasher = Sha256::new();
+    for file in files {
+      hasher.update(tokio::fs::read(&file).await?);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+  }
+
+  /// Resolves all meta files, folders are expanded to the meta files they contain.
+  async fn meta_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for path in &self.paths {
+      if !tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .is_dir()
+      {
+        files.push(path.clone());
+        continue;
+      }
+
+      let mut fragments = Vec::new();
+      let mut entries = tokio::fs::read_dir(path).await?;
+      while let Some(entry) = entries.next_entry().await? {
+        let file = entry.path();
+        // follows symlinks, e.g. of a mounted kubernetes config map
+        if is_meta_file(&file) && tokio::fs::metadata(&file).await?.is_file() {
+          fragments.push(file);
+        }
+      }
+
+      if fragments.is_empty() {
+        anyhow::bail!("Meta folder {} contains no meta files", path.display());
+      }
+
+      fragments.sort();
+      files.extend(fragments);
+    }
+
+    // the file of the runtime api is loaded on its own, even if it is part of a meta folder
+    if let Some(api_path) = self.api.as_ref().and_then(ApiMeta::path) {
+      let api_path = std::path::absolute(api_path)?;
+      files.retain(|file| std::path::absolute(file).ok().as_ref() != Some(&api_path));
+    }
+
+    Ok(files)
+  }
+}
+
+pub(super) fn is_meta_file(path: &Path) -> bool {
+  matches!(
+    path.extension().and_then(|extension| extension.to_str()),
+    Some("yaml" | "yml" | "json" | "toml")
+  )
+}
+
+/// JSON Schema of meta files.
+pub(super) fn schema() -> anyhow::Result<String> {
+  Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+    MetaStorage
+  ))?)
+}
+
+impl Router {
+  /// Checks if the router is pinned to locations, which do not include the given one.
+  pub(super) fn is_misplaced(&self, agent: &str, port: &str) -> bool {
+    !self.pinned.is_empty()
+      && !self.pinned.iter().any(|location| {
+        location.agent == agent && location.port.as_ref().is_none_or(|pinned| pinned == port)
+      })
+  }
+}
+
+impl Agent {
+  pub(super) fn get_ports(&self) -> Values<'_, u32, Port> {
+    self.ports.values()
+  }
+
+  pub(super) fn lookup_port(&self, if_idx: u32) -> Option<&Port> {
+    self.ports.get(&if_idx)
+  }
+
+  /// Ports that are not configured are treated as edge ports.
+  pub(super) fn port_role(&self, if_idx: u32) -> PortRole {
+    self
+      .lookup_port(if_idx)
+      .map(|port| port.role)
+      .unwrap_or_default()
+  }
+}
+
+fn check_conflict<K: Hash + Eq>(
+  origins: &mut HashMap<K, PathBuf>,
+  key: K,
+  file: &Path,
+  name: impl FnOnce() -> String,
+) -> anyhow::Result<()> {
+  match origins.insert(key, file.to_path_buf()) {
+    Some(origin) if origin != file => anyhow::bail!(
+      "{} is defined in both {} and {}",
+      name(),
+      origin.display(),
+      file.display()
+    ),
+    _ => Ok(()),
+  }
+}
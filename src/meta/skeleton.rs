@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::discover::Discovered;
+use crate::meta::ether_type::{registry_name, EtherTypeStorage, UnknownEtherTypes};
+use crate::meta::format::MetaFormat;
+use crate::meta::ixf::IxfStorage;
+use crate::meta::mac::MacPattern;
+use crate::meta::{
+  AgentSourceStorage, AgentStorage, CountingPolicy, MetaStorage, MtuStorage, RouterStorage,
+};
+use crate::utils::{fmt_bytes, fmt_mac};
+
+#[derive(Serialize)]
+struct Skeleton {
+  routers: Vec<RouterStorage>,
+  agents: Vec<AgentStorage>,
+  ether_types: BTreeMap<u16, EtherTypeStorage>,
+  #[serde(flatten)]
+  settings: Settings,
+}
+
+#[derive(Serialize)]
+struct Settings {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  unknown_ether_types: Option<UnknownEtherTypes>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  counting: Option<CountingPolicy>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  mtu: Option<MtuStorage>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ixf: Option<IxfStorage>,
+}
+
+/// Renders a meta file of the discovered agents, routers and ether types, in the format of the
+/// existing meta file. Yaml is annotated with the bytes they accounted for, as json has no
+/// comments. Everything defined by the existing meta file is kept.
+pub(crate) async fn render_skeleton(
+  existing: Option<&Path>,
+  format: MetaFormat,
+  seconds: u64,
+  discovered: &Discovered,
+) -> anyhow::Result<String> {
+  let meta = match existing {
+    Some(path) => {
+      let raw_meta = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+      MetaFormat::of(path)
+        .parse::<MetaStorage>(&raw_meta)
+        .with_context(|| format!("Failed to parse {}", path.display()))?
+    }
+    None => MetaStorage::default(),
+  };
+
+  // discovered macs are accounted to the most specific existing router
+  let patterns = meta
+    .routers
+    .iter()
+    .map(|router| router.mac_pattern().ok().flatten())
+    .collect::<Vec<_>>();
+  let mut router_bytes = vec![0; meta.routers.len()];
+  let mut new_routers = Vec::new();
+  for (mac, bytes) in &discovered.routers {
+    let matching = patterns
+      .iter()
+      .enumerate()
+      .filter_map(|(i, pattern)| pattern.map(|pattern| (i, pattern)))
+      .filter(|(_, pattern)| MacPattern::of(mac, pattern.len()) == *pattern)
+      .max_by_key(|(_, pattern)| pattern.len());
+    match matching {
+      Some((i, _)) => router_bytes[i] += bytes,
+      None => new_routers.push((
+        RouterStorage {
+          mac: Some(fmt_mac(mac)),
+          addrs: Vec::new(),
+          label: fmt_mac(mac),
+          pinned: Vec::new(),
+        },
+        *bytes,
+      )),
+    }
+  }
+  new_routers.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+  let prefixes = meta
+    .agents
+    .iter()
+    .map(|agent| agent.source.prefix())
+    .collect::<Vec<_>>();
+  let mut agent_bytes = vec![0; meta.agents.len()];
+  let mut new_agents = Vec::new();
+  for (addr, bytes) in &discovered.agents {
+    let matching = prefixes
+      .iter()
+      .enumerate()
+      .filter(|(_, prefix)| prefix.contains(addr))
+      .max_by_key(|(_, prefix)| prefix.prefix_len());
+    match matching {
+      Some((i, _)) => agent_bytes[i] += bytes,
+      None => new_agents.push((
+        AgentStorage {
+          label: addr.to_string(),
+          source: AgentSourceStorage::Addr(*addr),
+          ports: Default::default(),
+        },
+        *bytes,
+      )),
+    }
+  }
+  new_agents.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+  let mut ether_types = meta
+    .ether_types
+    .resolve()?
+    .into_iter()
+    .collect::<BTreeMap<_, _>>();
+  for id in discovered.ether_types.keys() {
+    ether_types.entry(*id).or_insert_with(|| {
+      registry_name(*id)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("{:#06x}", id))
+    });
+  }
+
+  let settings = Settings {
+    unknown_ether_types: meta.unknown_ether_types,
+    counting: meta.counting,
+    mtu: meta.mtu,
+    ixf: meta.ixf,
+  };
+  let routers = meta
+    .routers
+    .into_iter()
+    .zip(router_bytes)
+    .chain(new_routers);
+  let agents = meta.agents.into_iter().zip(agent_bytes).chain(new_agents);
+
+  if !matches!(format, MetaFormat::Yaml) {
+    let skeleton = Skeleton {
+      routers: routers.map(|(router, _)| router).collect(),
+      agents: agents.map(|(agent, _)| agent).collect(),
+      ether_types: ether_types
+        .into_iter()
+        .map(|(id, label)| (id, EtherTypeStorage { label }))
+        .collect(),
+      settings,
+    };
+    let mut out = format.serialize(&skeleton)?;
+    if !out.ends_with('\n') {
+      out.push('\n');
+    }
+    return Ok(out);
+  }
+
+  let mut out = String::new();
+  writeln!(
+    out,
+    "# Discovered by {} within {} seconds, byte volumes are extrapolated from samples.",
+    env!("CARGO_PKG_NAME"),
+    seconds
+  )?;
+
+  out.push_str("routers:");
+  push_items(&mut out, routers)?;
+
+  out.push_str("agents:");
+  push_items(&mut out, agents)?;
+
+  out.push_str("ether_types:");
+  if ether_types.is_empty() {
+    out.push_str(" {}");
+  }
+  out.push('\n');
+  for (id, label) in ether_types {
+    let bytes = discovered.ether_types.get(&id).copied().unwrap_or_default();
+    writeln!(out, "  {:#06x}:{}", id, fmt_comment(bytes))?;
+    push_indented(&mut out, &EtherTypeStorage { label }, "    ")?;
+  }
+
+  if settings.unknown_ether_types.is_some()
+    || settings.counting.is_some()
+    || settings.mtu.is_some()
+    || settings.ixf.is_some()
+  {
+    out.push_str(&serde_yaml_ng::to_string(&settings)?);
+  }
+
+  Ok(out)
+}
+
+fn push_items<T: Serialize>(
+  out: &mut String,
+  items: impl Iterator<Item = (T, u64)>,
+) -> anyhow::Result<()> {
+  let mut empty = true;
+  for (item, bytes) in items {
+    if empty {
+      out.push('\n');
+      empty = false;
+    }
+
+    let yaml = serde_yaml_ng::to_string(&item)?;
+    for (i, line) in yaml.lines().enumerate() {
+      if i == 0 {
+        writeln!(out, "  - {}{}", line, fmt_comment(bytes))?;
+      } else {
+        writeln!(out, "    {}", line)?;
+      }
+    }
+  }
+
+  if empty {
+    out.push_str(" []\n");
+  }
+
+  Ok(())
+}
+
+fn push_indented<T: Serialize>(out: &mut String, value: &T, indent: &str) -> anyhow::Result<()> {
+  for line in serde_yaml_ng::to_string(value)?.lines() {
+    writeln!(out, "{}{}", indent, line)?;
+  }
+  Ok(())
+}
+
+fn fmt_comment(bytes: u64) -> String {
+  if bytes == 0 {
+    String::new()
+  } else {
+    format!("  # {}", fmt_bytes(bytes))
+  }
+}
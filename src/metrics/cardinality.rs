@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use prometheus::core::Collector;
+use prometheus::{IntCounter, IntCounterVec};
+use serde::Deserialize;
+use tracing::warn;
+
+/// Label of the router, rare router pairs are aggregated into.
+pub(crate) const OTHER_ROUTER: &str = "other";
+
+/// Decides which series of `sflow_router_bytes` and `sflow_router_packets` are created before any traffic is seen.
+#[derive(Clone, Copy, Default, ValueEnum, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Preseed {
+  /// Series are created once traffic is seen.
+  None,
+  /// Series of all ether types are created for router pairs once they exchange traffic.
+  Observed,
+  /// Series of all ether types are created for every router pair.
+  #[default]
+  Full,
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct CardinalityOptions {
+  pub(crate) preseed: Preseed,
+  /// Upper bound of `sflow_router_bytes` series, `sflow_router_packets` has the same ones.
+  pub(crate) max_series: Option<usize>,
+  /// Bytes a router pair has to exchange before it gets its own series.
+  pub(crate) rare_pair_bytes: Option<u64>,
+}
+
+/// Keeps track of the series of `sflow_router_bytes` and `sflow_router_packets`, to keep their
+/// cardinality in check.
+pub(super) struct RouterSeries {
+  router_bytes: IntCounterVec,
+  router_packets: IntCounterVec,
+  overflow_bytes: IntCounter,
+  overflow_packets: IntCounter,
+  options: CardinalityOptions,
+  state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+  // only tracked with a series limit, keyed by the labels in, out and ether type, nested so they
+  // are looked up without allocating
+  series: HashMap<String, HashMap<String, HashSet<String>>>,
+  series_count: usize,
+  // only tracked if required by the pre-seed strategy or the aggregation of rare pairs
+  pairs: HashMap<String, HashSet<String>>,
+  rare_pairs: HashMap<String, HashMap<String, u64>>,
+  ether_types: Vec<String>,
+  limit_reached: bool,
+}
+
+impl RouterSeries {
+  pub(super) fn new(
+    router_bytes: IntCounterVec,
+    router_packets: IntCounterVec,
+    overflow_bytes: IntCounter,
+    overflow_packets: IntCounter,
+    options: CardinalityOptions,
+  ) -> Self {
+    Self {
+      router_bytes,
+      router_packets,
+      overflow_bytes,
+      overflow_packets,
+      options,
+      state: Mutex::default(),
+    }
+  }
+
+  pub(super) fn capture(
+    &self,
+    r#in: &str,
+    r#out: &str,
+    ether_type: &str,
+    bytes: u64,
+    packets: u64,
+  ) {
+    let mut state = self.state.lock().unwrap();
+
+    if !self.tracks_pairs() {
+      self.inc(&mut state, r#in, r#out, ether_type, bytes, packets);
+      return;
+    }
+
+    let is_tracked = state
+      .pairs
+      .get(r#in)
+      .is_some_and(|outs| outs.contains(r#out));
+    if !is_tracked {
+      if let Some(rare_pair_bytes) = self.options.rare_pair_bytes {
+        let exchanged = get_or_default(get_or_default(&mut state.rare_pairs, r#in), r#out);
+        *exchanged += bytes;
+
+        if *exchanged < rare_pair_bytes {
+          self.inc(
+            &mut state,
+            OTHER_ROUTER,
+            OTHER_ROUTER,
+            ether_type,
+            bytes,
+            packets,
+          );
+          return;
+        }
+
+        // bytes exchanged so far stay accounted to the other router
+        if let Some(outs) = state.rare_pairs.get_mut(r#in) {
+          outs.remove(r#out);
+        }
+      }
+
+      get_or_default(&mut state.pairs, r#in).insert(r#out.to_string());
+
+      if self.options.preseed == Preseed::Observed {
+        for seeded_ether_type in state.ether_types.clone() {
+          self.seed(&mut state, r#in, r#out, &seeded_ether_type);
+        }
+      }
+    }
+
+    self.inc(&mut state, r#in, r#out, ether_type, bytes, packets);
+  }
+
+  /// Checks if the traffic of a router pair is aggregated into the other router, because they
+  /// have not exchanged enough bytes yet.
+  pub(super) fn is_rare_pair(&self, r#in: &str, r#out: &str) -> bool {
+    self.options.rare_pair_bytes.is_some()
+      && !self
+        .state
+        .lock()
+        .unwrap()
+        .pairs
+        .get(r#in)
+        .is_some_and(|outs| outs.contains(r#out))
+  }
+
+  /// Creates the series according to the pre-seed strategy for a newly loaded meta configuration.
+  pub(super) fn preseed(&self, routers: &HashSet<&str>, ether_types: &HashSet<&str>) {
+    let mut state = self.state.lock().unwrap();
+    state.ether_types = ether_types.iter().map(|label| label.to_string()).collect();
+    // pairs, which never reach the threshold, would otherwise pile up
+    state.rare_pairs.clear();
+
+    let pairs = match self.options.preseed {
+      Preseed::None => return,
+      Preseed::Observed => state
+        .pairs
+        .iter()
+        .filter(|(r#in, _)| routers.contains(r#in.as_str()))
+        .flat_map(|(r#in, outs)| {
+          outs
+            .iter()
+            .filter(|r#out| routers.contains(r#out.as_str()))
+            .map(|r#out| (r#in.clone(), r#out.clone()))
+        })
+        .collect::<Vec<_>>(),
+      Preseed::Full => routers
+        .iter()
+        .flat_map(|r#in| {
+          routers
+            .iter()
+            .map(|r#out| (r#in.to_string(), r#out.to_string()))
+        })
+        .collect(),
+    };
+
+    for (r#in, r#out) in pairs {
+      for ether_type in ether_types {
+        self.seed(&mut state, &r#in, &r#out, ether_type);
+      }
+    }
+  }
+
+  /// Forgets about series and pairs that have been removed.
+  pub(super) fn retain(&self, routers: &HashSet<&str>) {
+    let mut state = self.state.lock().unwrap();
+
+    state.series.clear();
+    state.series_count = 0;
+    let families = match self.options.max_series {
+      Some(_) => self.router_bytes.collect(),
+      None => Vec::new(),
+    };
+    for family in families {
+      for metric in family.get_metric() {
+        let labels = metric
+          .get_label()
+          .iter()
+          .map(|label| (label.name(), label.value()))
+          .collect::<HashMap<_, _>>();
+        let outs = get_or_default(&mut state.series, labels["in"]);
+        if get_or_default(outs, labels["out"]).insert(labels["ether_type"].to_string()) {
+          state.series_count += 1;
+        }
+      }
+    }
+    state.limit_reached = false;
+
+    state.pairs.retain(|r#in, outs| {
+      outs.retain(|r#out| routers.contains(r#out.as_str()));
+      routers.contains(r#in.as_str()) && !outs.is_empty()
+    });
+  }
+
+  /// Creates a series without traffic. With a series limit, seeding stops at half of it, so
+  /// series carrying traffic are not crowded out by empty ones.
+  fn seed(&self, state: &mut State, r#in: &str, r#out: &str, ether_type: &str) {
+    if let Some(max_series) = self.options.max_series {
+      if state.series_count >= max_series / 2 {
+        return;
+      }
+    }
+
+    self.inc(state, r#in, r#out, ether_type, 0, 0);
+  }
+
+  fn tracks_pairs(&self) -> bool {
+    self.options.preseed == Preseed::Observed || self.options.rare_pair_bytes.is_some()
+  }
+
+  fn inc(
+    &self,
+    state: &mut State,
+    r#in: &str,
+    r#out: &str,
+    ether_type: &str,
+    bytes: u64,
+    packets: u64,
+  ) {
+    if let Some(max_series) = self.options.max_series {
+      let is_known = state
+        .series
+        .get(r#in)
+        .and_then(|outs| outs.get(r#out))
+        .is_some_and(|ether_types| ether_types.contains(ether_type));
+
+      if !is_known {
+        if state.series_count >= max_series {
+          if !state.limit_reached {
+            warn!("Reached the limit of {} router series", max_series);
+            state.limit_reached = true;
+          }
+          self.overflow_bytes.inc_by(bytes);
+          self.overflow_packets.inc_by(packets);
+          return;
+        }
+
+        let outs = get_or_default(&mut state.series, r#in);
+        get_or_default(outs, r#out).insert(ether_type.to_string());
+        state.series_count += 1;
+      }
+    }
+
+    self
+      .router_bytes
+      .with_label_values(&[r#in, r#out, ether_type])
+      .inc_by(bytes);
+    self
+      .router_packets
+      .with_label_values(&[r#in, r#out, ether_type])
+      .inc_by(packets);
+  }
+}
+
+/// Looks up the value of a key, inserting the default first if it is missing. Unlike the entry
+/// api, the key is only allocated if it is missing.
+fn get_or_default<'a, T: Default>(map: &'a mut HashMap<String, T>, key: &str) -> &'a mut T {
+  if !map.contains_key(key) {
+    map.insert(key.to_string(), T::default());
+  }
+  map.get_mut(key).unwrap()
+}
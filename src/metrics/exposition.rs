@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use prometheus::proto::{LabelPair, MetricFamily};
+use prometheus::{Encoder, ProtobufEncoder, Registry, TextEncoder};
+
+use crate::metrics::openmetrics::{self, CreatedTimestamps, OPENMETRICS_FORMAT};
+
+/// Prefix of all metric names, which may be replaced by the namespace.
+const DEFAULT_NAMESPACE: &str = "sflow";
+
+/// Label names of all metrics, which may be renamed.
+pub(crate) const LABEL_NAMES: [&str; 14] = [
+  "in",
+  "out",
+  "ether_type",
+  "agent",
+  "port",
+  "direction",
+  "router",
+  "hash",
+  "family",
+  "source_id",
+  "type",
+  "format",
+  "kind",
+  "version",
+];
+
+/// Naming of the exported metrics, it is applied whenever the registry is gathered.
+#[derive(Default)]
+pub(crate) struct MetricNaming {
+  /// Replaces the `sflow` prefix of all metric names.
+  pub(crate) namespace: Option<String>,
+  /// New names of label names.
+  pub(crate) label_names: HashMap<String, String>,
+  /// Labels added to every series.
+  pub(crate) const_labels: BTreeMap<String, String>,
+}
+
+/// The registry as exposed to prometheus.
+#[derive(Clone)]
+pub(crate) struct Exposition {
+  registry: Registry,
+  naming: Arc<MetricNaming>,
+  // only offered if enabled, because counters gain a `_total` suffix
+  openmetrics: bool,
+  created: Arc<Mutex<CreatedTimestamps>>,
+}
+
+/// Formats of the exposition, negotiated with the scraper.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+  Text,
+  OpenMetrics,
+  Protobuf,
+}
+
+impl Exposition {
+  pub(crate) fn new(registry: Registry, naming: MetricNaming, openmetrics: bool) -> Self {
+    Self {
+      registry,
+      naming: Arc::new(naming),
+      openmetrics,
+      created: Arc::new(Mutex::new(CreatedTimestamps::new())),
+    }
+  }
+
+  /// Picks the format the `Accept` header prefers most, the text format if none is supported.
+  pub(crate) fn negotiate(&self, accept: Option<&str>) -> Format {
+    let mut format = Format::Text;
+    let mut max_quality = 0.0;
+
+    for media_range in accept.unwrap_or_default().split(',') {
+      let mut params = media_range.split(';').map(str::trim);
+      let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+      let mut quality = 1.0;
+      let mut is_delimited_family = [false, false];
+      for param in params {
+        match param.split_once('=') {
+          Some(("q", value)) => quality = value.parse().unwrap_or(0.0),
+          Some(("proto", "io.prometheus.client.MetricFamily")) => is_delimited_family[0] = true,
+          Some(("encoding", "delimited")) => is_delimited_family[1] = true,
+          _ => {}
+        }
+      }
+
+      let candidate = match media_type.as_str() {
+        "application/openmetrics-text" if self.openmetrics => Format::OpenMetrics,
+        "application/vnd.google.protobuf" if is_delimited_family == [true, true] => {
+          Format::Protobuf
+        }
+        "text/plain" | "text/*" | "*/*" => Format::Text,
+        _ => continue,
+      };
+      if quality > max_quality {
+        format = candidate;
+        max_quality = quality;
+      }
+    }
+
+    format
+  }
+
+  /// Gathers and encodes the registry, returns the content type and body.
+  pub(crate) fn encode(&self, format: Format) -> prometheus::Result<(&'static str, Vec<u8>)> {
+    let families = self.gather();
+    match format {
+      Format::Text => {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&families, &mut buf)?;
+        Ok((prometheus::TEXT_FORMAT, buf))
+      }
+      Format::OpenMetrics => {
+        let mut created = self.created.lock().unwrap();
+        let buf = openmetrics::encode(&families, &mut created);
+        Ok((OPENMETRICS_FORMAT, buf.into_bytes()))
+      }
+      Format::Protobuf => {
+        let mut buf = Vec::new();
+        ProtobufEncoder::new().encode(&families, &mut buf)?;
+        Ok((prometheus::PROTOBUF_FORMAT, buf))
+      }
+    }
+  }
+
+  pub(crate) fn gather(&self) -> Vec<MetricFamily> {
+    let mut families = self.registry.gather();
+    let naming = &self.naming;
+
+    for family in &mut families {
+      if let Some(namespace) = &naming.namespace {
+        if let Some(name) = family.name().strip_prefix(DEFAULT_NAMESPACE) {
+          family.set_name(format!("{}{}", namespace, name));
+        }
+      }
+
+      if naming.label_names.is_empty() && naming.const_labels.is_empty() {
+        continue;
+      }
+
+      for metric in family.mut_metric() {
+        let mut labels = metric.take_label();
+        for label in &mut labels {
+          if let Some(name) = naming.label_names.get(label.name()) {
+            label.set_name(name.clone());
+          }
+        }
+        labels.extend(naming.const_labels.iter().map(|(name, value)| {
+          let mut label = LabelPair::default();
+          label.set_name(name.clone());
+          label.set_value(value.clone());
+          label
+        }));
+        metric.set_label(labels);
+      }
+    }
+
+    families
+  }
+}
+
+/// Checks if a metric name prefix or label name is valid, label names may not contain colons.
+pub(crate) fn is_valid_name(name: &str, is_label: bool) -> bool {
+  let mut chars = name.chars();
+  let is_valid_char =
+    |char: char| char.is_ascii_alphanumeric() || char == '_' || (!is_label && char == ':');
+
+  chars
+    .next()
+    .is_some_and(|first| !first.is_ascii_digit() && is_valid_char(first))
+    && chars.all(is_valid_char)
+    && !(is_label && name.starts_with("__"))
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{Bucket, Histogram, LabelPair, Metric, MetricFamily, MetricType};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::metrics::cardinality::OTHER_ROUTER;
+
+/// IMIX-style buckets, covering minimum sized, common and jumbo frames.
+pub(crate) const DEFAULT_FRAME_SIZE_BUCKETS: [u64; 10] =
+  [64, 128, 256, 512, 576, 1024, 1518, 2048, 4096, 9216];
+
+/// Labels `sflow_router_frame_size_bytes` may be partitioned by.
+#[derive(Clone, Copy, ValueEnum, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FrameSizeLabel {
+  /// The sending router.
+  In,
+  /// The receiving router.
+  Out,
+  #[value(name = "ether_type")]
+  EtherType,
+}
+
+pub(crate) struct FrameSizeOptions {
+  pub(crate) labels: Vec<FrameSizeLabel>,
+  /// Upper bounds of the buckets in bytes, ascending.
+  pub(crate) buckets: Vec<u64>,
+}
+
+/// Histogram of sampled frame sizes. Unlike the histograms of the prometheus crate, every
+/// observation is weighted with the sampling rate.
+#[derive(Clone)]
+pub(super) struct FrameSizes {
+  desc: Desc,
+  labels: Vec<FrameSizeLabel>,
+  buckets: Vec<u64>,
+  /// Upper bound of series, the routers of further ones are aggregated into `other`.
+  max_series: Option<usize>,
+  series: Arc<Mutex<HashMap<Vec<String>, Series>>>,
+  limit_reached: Arc<AtomicBool>,
+}
+
+struct Series {
+  // not cumulative, the last one counts frames above all buckets
+  counts: Vec<u64>,
+  sum: u64,
+}
+
+impl FrameSizes {
+  pub(super) fn new(options: FrameSizeOptions, max_series: Option<usize>) -> Self {
+    let desc = Desc::new(
+      "sflow_router_frame_size_bytes".to_string(),
+      "sampled frame sizes, weighted by the sampling rate".to_string(),
+      options
+        .labels
+        .iter()
+        .map(|label| label.name().to_string())
+        .collect(),
+      HashMap::new(),
+    )
+    .unwrap();
+
+    Self {
+      desc,
+      labels: options.labels,
+      buckets: options.buckets,
+      max_series,
+      series: Arc::default(),
+      limit_reached: Arc::default(),
+    }
+  }
+
+  /// Observes a frame, unless a router it is partitioned by is unknown.
+  pub(super) fn observe(
+    &self,
+    r#in: Option<&str>,
+    r#out: Option<&str>,
+    ether_type: &str,
+    frame_length: u64,
+    frames: u64,
+  ) {
+    let values = self
+      .labels
+      .iter()
+      .map(|label| match label {
+        FrameSizeLabel::In => r#in.map(str::to_string),
+        FrameSizeLabel::Out => r#out.map(str::to_string),
+        FrameSizeLabel::EtherType => Some(ether_type.to_string()),
+      })
+      .collect::<Option<Vec<_>>>();
+    let mut values = match values {
+      Some(values) => values,
+      None => return,
+    };
+
+    let bucket = self
+      .buckets
+      .iter()
+      .position(|upper_bound| frame_length <= *upper_bound)
+      .unwrap_or(self.buckets.len());
+
+    let mut series = self.series.lock().unwrap();
+    if let Some(max_series) = self.max_series {
+      // series of the other router are bounded by the ether types, so they are always created
+      if series.len() >= max_series && !series.contains_key(&values) {
+        if !self.limit_reached.swap(true, Ordering::Relaxed) {
+          warn!("Reached the limit of {} frame size series", max_series);
+        }
+        for (label, value) in self.labels.iter().zip(&mut values) {
+          if *label != FrameSizeLabel::EtherType {
+            *value = OTHER_ROUTER.to_string();
+          }
+        }
+      }
+    }
+    let series = series.entry(values).or_insert_with(|| Series {
+      counts: vec![0; self.buckets.len() + 1],
+      sum: 0,
+    });
+    series.counts[bucket] += frames;
+    series.sum += frame_length * frames;
+  }
+
+  /// Removes all series whose labels are considered stale.
+  pub(super) fn retain(&self, is_stale: impl Fn(&HashMap<&str, &str>) -> bool) {
+    self.limit_reached.store(false, Ordering::Relaxed);
+    self.series.lock().unwrap().retain(|values, _| {
+      let labels = self
+        .labels
+        .iter()
+        .map(|label| label.name())
+        .zip(values.iter().map(String::as_str))
+        .collect::<HashMap<_, _>>();
+      !is_stale(&labels)
+    });
+  }
+}
+
+impl FrameSizeLabel {
+  fn name(&self) -> &'static str {
+    match self {
+      FrameSizeLabel::In => "in",
+      FrameSizeLabel::Out => "out",
+      FrameSizeLabel::EtherType => "ether_type",
+    }
+  }
+}
+
+impl Collector for FrameSizes {
+  fn desc(&self) -> Vec<&Desc> {
+    vec![&self.desc]
+  }
+
+  fn collect(&self) -> Vec<MetricFamily> {
+    let metrics = self
+      .series
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|(values, series)| {
+        let mut cumulative_count = 0;
+        let buckets = self
+          .buckets
+          .iter()
+          .zip(&series.counts)
+          .map(|(upper_bound, count)| {
+            cumulative_count += count;
+            let mut bucket = Bucket::default();
+            bucket.set_upper_bound(*upper_bound as f64);
+            bucket.set_cumulative_count(cumulative_count);
+            bucket
+          })
+          .collect();
+
+        let mut histogram = Histogram::default();
+        histogram.set_bucket(buckets);
+        histogram.set_sample_count(series.counts.iter().sum());
+        histogram.set_sample_sum(series.sum as f64);
+
+        let labels = self
+          .labels
+          .iter()
+          .zip(values)
+          .map(|(label, value)| {
+            let mut pair = LabelPair::default();
+            pair.set_name(label.name().to_string());
+            pair.set_value(value.clone());
+            pair
+          })
+          .collect();
+
+        let mut metric = Metric::from_label(labels);
+        metric.set_histogram(histogram);
+        metric
+      })
+      .collect();
+
+    let mut family = MetricFamily::default();
+    family.set_name(self.desc.fq_name.clone());
+    family.set_help(self.desc.help.clone());
+    family.set_field_type(MetricType::HISTOGRAM);
+    family.set_metric(metrics);
+    vec![family]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn routers(frame_sizes: &FrameSizes) -> Vec<(String, String, u64)> {
+    let mut routers = frame_sizes.collect()[0]
+      .get_metric()
+      .iter()
+      .map(|metric| {
+        let labels = metric.get_label();
+        (
+          labels[0].value().to_string(),
+          labels[1].value().to_string(),
+          metric.get_histogram().get_sample_count(),
+        )
+      })
+      .collect::<Vec<_>>();
+    routers.sort();
+    routers
+  }
+
+  #[test]
+  fn aggregates_routers_beyond_the_series_limit() {
+    let options = FrameSizeOptions {
+      labels: vec![FrameSizeLabel::In, FrameSizeLabel::Out],
+      buckets: DEFAULT_FRAME_SIZE_BUCKETS.to_vec(),
+    };
+    let frame_sizes = FrameSizes::new(options, Some(2));
+
+    frame_sizes.observe(Some("a"), Some("b"), "IPv4", 64, 1);
+    frame_sizes.observe(Some("b"), Some("a"), "IPv4", 64, 1);
+    frame_sizes.observe(Some("a"), Some("c"), "IPv4", 64, 1);
+    frame_sizes.observe(Some("c"), Some("a"), "IPv4", 64, 1);
+    // existing series keep being observed
+    frame_sizes.observe(Some("a"), Some("b"), "IPv4", 64, 1);
+
+    assert_eq!(
+      routers(&frame_sizes),
+      [
+        ("a".to_string(), "b".to_string(), 2),
+        ("b".to_string(), "a".to_string(), 1),
+        ("other".to_string(), "other".to_string(), 2),
+      ]
+    );
+  }
+}
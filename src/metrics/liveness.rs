@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{Gauge, LabelPair, Metric, MetricFamily, MetricType};
+
+/// Tracks when agents were last heard of. Whether an agent is up is decided at scrape time, so it
+/// goes down even if no datagrams arrive at all.
+#[derive(Clone)]
+pub(super) struct AgentLiveness {
+  last_datagram_desc: Desc,
+  up_desc: Desc,
+  timeout: Duration,
+  // agents of the meta configuration, which have not been heard of yet, have no timestamp
+  agents: Arc<Mutex<HashMap<String, Option<SystemTime>>>>,
+}
+
+impl AgentLiveness {
+  pub(super) fn new(timeout: Duration) -> Self {
+    Self {
+      last_datagram_desc: Desc::new(
+        "sflow_agent_last_datagram_timestamp_seconds".to_string(),
+        "timestamp of the last datagram received from the agent".to_string(),
+        vec!["agent".to_string()],
+        HashMap::new(),
+      )
+      .unwrap(),
+      up_desc: Desc::new(
+        "sflow_agent_up".to_string(),
+        "whether a datagram was received from the agent within the agent timeout".to_string(),
+        vec!["agent".to_string()],
+        HashMap::new(),
+      )
+      .unwrap(),
+      timeout,
+      agents: Arc::default(),
+    }
+  }
+
+  pub(super) fn capture_datagram(&self, agent: &str) {
+    let mut agents = self.agents.lock().unwrap();
+    match agents.get_mut(agent) {
+      Some(last_datagram) => *last_datagram = Some(SystemTime::now()),
+      None => {
+        agents.insert(agent.to_string(), Some(SystemTime::now()));
+      }
+    }
+  }
+
+  /// Adds agents of a newly loaded meta configuration, so they are reported as down until they
+  /// are heard of.
+  pub(super) fn preseed<'a>(&self, agents: impl Iterator<Item = &'a str>) {
+    let mut known = self.agents.lock().unwrap();
+    for agent in agents {
+      known.entry(agent.to_string()).or_default();
+    }
+  }
+
+  pub(super) fn retain(&self, agents: &HashSet<&str>) {
+    self
+      .agents
+      .lock()
+      .unwrap()
+      .retain(|agent, _| agents.contains(agent.as_str()));
+  }
+}
+
+impl Collector for AgentLiveness {
+  fn desc(&self) -> Vec<&Desc> {
+    vec![&self.last_datagram_desc, &self.up_desc]
+  }
+
+  fn collect(&self) -> Vec<MetricFamily> {
+    let now = SystemTime::now();
+    let agents = self.agents.lock().unwrap();
+
+    let mut last_datagram = Vec::new();
+    let mut up = Vec::new();
+    for (agent, timestamp) in agents.iter() {
+      if let Some(timestamp) = timestamp {
+        let seconds = timestamp
+          .duration_since(UNIX_EPOCH)
+          .map(|duration| duration.as_secs_f64())
+          .unwrap_or_default();
+        last_datagram.push(gauge(agent, seconds));
+      }
+
+      let is_up = timestamp.is_some_and(|timestamp| {
+        now
+          .duration_since(timestamp)
+          .is_ok_and(|elapsed| elapsed <= self.timeout)
+      });
+      up.push(gauge(agent, if is_up { 1.0 } else { 0.0 }));
+    }
+
+    vec![
+      family(&self.last_datagram_desc, last_datagram),
+      family(&self.up_desc, up),
+    ]
+  }
+}
+
+fn gauge(agent: &str, value: f64) -> Metric {
+  let mut label = LabelPair::default();
+  label.set_name("agent".to_string());
+  label.set_value(agent.to_string());
+
+  let mut gauge = Gauge::default();
+  gauge.set_value(value);
+
+  let mut metric = Metric::from_label(vec![label]);
+  metric.set_gauge(gauge);
+  metric
+}
+
+fn family(desc: &Desc, metrics: Vec<Metric>) -> MetricFamily {
+  let mut family = MetricFamily::default();
+  family.set_name(desc.fq_name.clone());
+  family.set_help(desc.help.clone());
+  family.set_field_type(MetricType::GAUGE);
+  family.set_metric(metrics);
+  family
+}
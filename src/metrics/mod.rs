@@ -0,0 +1,397 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prometheus::core::{Collector, MetricVec, MetricVecBuilder};
+use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+
+use crate::meta::Meta;
+use crate::metrics::cardinality::{CardinalityOptions, RouterSeries, OTHER_ROUTER};
+use crate::metrics::frame_size::{FrameSizeOptions, FrameSizes};
+use crate::metrics::liveness::AgentLiveness;
+use crate::metrics::pipeline::Pipeline;
+
+pub(crate) mod cardinality;
+pub(crate) mod exposition;
+pub(crate) mod frame_size;
+mod liveness;
+mod openmetrics;
+pub(crate) mod pipeline;
+pub(crate) mod remote_write;
+
+pub(super) struct Metrics {
+  router_bytes: IntCounterVec,
+  router_packets: IntCounterVec,
+  router_series: RouterSeries,
+  frame_sizes: Option<FrameSizes>,
+  agent_drops: IntCounterVec,
+  agent_sample_rate: IntGaugeVec,
+  agent_liveness: AgentLiveness,
+  port_bytes: IntCounterVec,
+  router_mac_violations: IntCounterVec,
+  router_mtu_violations: IntCounterVec,
+  meta_reload_success: IntGauge,
+  meta_last_reload_timestamp: IntGauge,
+  meta_config_info: IntGaugeVec,
+  learned_neighbors: IntGaugeVec,
+  pipeline: Pipeline,
+}
+
+impl Metrics {
+  pub(super) fn new(
+    cardinality: CardinalityOptions,
+    frame_sizes: Option<FrameSizeOptions>,
+    agent_timeout: Duration,
+  ) -> (Self, Registry) {
+    let router_bytes = IntCounterVec::new(
+      Opts::new("sflow_router_bytes", "bytes"),
+      &["in", "out", "ether_type"],
+    )
+    .unwrap();
+    let router_packets = IntCounterVec::new(
+      Opts::new("sflow_router_packets", "packets"),
+      &["in", "out", "ether_type"],
+    )
+    .unwrap();
+    let router_bytes_overflow = IntCounter::new(
+      "sflow_router_bytes_overflow",
+      "bytes not accounted to routers, because the series limit was reached",
+    )
+    .unwrap();
+    let router_packets_overflow = IntCounter::new(
+      "sflow_router_packets_overflow",
+      "packets not accounted to routers, because the series limit was reached",
+    )
+    .unwrap();
+    let agent_drops =
+      IntCounterVec::new(Opts::new("sflow_agent_drops", "drops"), &["agent"]).unwrap();
+    let agent_sample_rate = IntGaugeVec::new(
+      Opts::new(
+        "sflow_agent_sample_rate",
+        "sampling rate of the last flow sample of a data source",
+      ),
+      &["agent", "source_id"],
+    )
+    .unwrap();
+    let agent_liveness = AgentLiveness::new(agent_timeout);
+    let port_bytes = IntCounterVec::new(
+      Opts::new("sflow_port_bytes", "bytes"),
+      &["agent", "port", "direction"],
+    )
+    .unwrap();
+    let router_mac_violations = IntCounterVec::new(
+      Opts::new("sflow_router_mac_violations_total", "mac violations"),
+      &["router", "agent", "port"],
+    )
+    .unwrap();
+    let router_mtu_violations = IntCounterVec::new(
+      Opts::new(
+        "sflow_router_mtu_violations_total",
+        "frames exceeding the mtu",
+      ),
+      &["router"],
+    )
+    .unwrap();
+    let meta_reload_success = IntGauge::new(
+      "sflow_meta_reload_success",
+      "whether the last meta reload was successful",
+    )
+    .unwrap();
+    let meta_last_reload_timestamp = IntGauge::new(
+      "sflow_meta_last_reload_timestamp_seconds",
+      "timestamp of the last successful meta reload",
+    )
+    .unwrap();
+    let meta_config_info = IntGaugeVec::new(
+      Opts::new(
+        "sflow_meta_config_info",
+        "hash of the running meta configuration",
+      ),
+      &["hash"],
+    )
+    .unwrap();
+
+    let learned_neighbors = IntGaugeVec::new(
+      Opts::new(
+        "sflow_learned_neighbors",
+        "ip to mac bindings learned from arp and neighbor discovery",
+      ),
+      &["family"],
+    )
+    .unwrap();
+    learned_neighbors.with_label_values(&["ipv4"]).set(0);
+    learned_neighbors.with_label_values(&["ipv6"]).set(0);
+    let frame_sizes =
+      frame_sizes.map(|frame_sizes| FrameSizes::new(frame_sizes, cardinality.max_series));
+
+    let registry = Registry::new();
+    registry.register(Box::new(router_bytes.clone())).unwrap();
+    registry.register(Box::new(router_packets.clone())).unwrap();
+    registry
+      .register(Box::new(router_bytes_overflow.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(router_packets_overflow.clone()))
+      .unwrap();
+    registry.register(Box::new(agent_drops.clone())).unwrap();
+    registry
+      .register(Box::new(agent_sample_rate.clone()))
+      .unwrap();
+    registry.register(Box::new(agent_liveness.clone())).unwrap();
+    registry.register(Box::new(port_bytes.clone())).unwrap();
+    registry
+      .register(Box::new(router_mac_violations.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(router_mtu_violations.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(meta_reload_success.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(meta_last_reload_timestamp.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(meta_config_info.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(learned_neighbors.clone()))
+      .unwrap();
+    if let Some(frame_sizes) = &frame_sizes {
+      registry.register(Box::new(frame_sizes.clone())).unwrap();
+    }
+    let pipeline = Pipeline::new(&registry);
+
+    (
+      Self {
+        router_series: RouterSeries::new(
+          router_bytes.clone(),
+          router_packets.clone(),
+          router_bytes_overflow,
+          router_packets_overflow,
+          cardinality,
+        ),
+        frame_sizes,
+        router_bytes,
+        router_packets,
+        agent_drops,
+        agent_sample_rate,
+        agent_liveness,
+        port_bytes,
+        router_mac_violations,
+        router_mtu_violations,
+        meta_reload_success,
+        meta_last_reload_timestamp,
+        meta_config_info,
+        learned_neighbors,
+        pipeline,
+      },
+      registry,
+    )
+  }
+
+  pub(super) fn pipeline(&self) -> &Pipeline {
+    &self.pipeline
+  }
+
+  pub(super) fn capture_router_traffic(
+    &self,
+    r#in: &str,
+    r#out: &str,
+    ether_type: &str,
+    bytes: u64,
+    packets: u64,
+  ) {
+    self
+      .router_series
+      .capture(r#in, r#out, ether_type, bytes, packets);
+  }
+
+  pub(super) fn capture_router_mtu_violations(&self, router: &str, frames: u64) {
+    self
+      .router_mtu_violations
+      .with_label_values(&[router])
+      .inc_by(frames);
+  }
+
+  /// Observes the size of a sampled frame, if the frame size histogram is enabled. Like the
+  /// router traffic, which has to be captured first, rare pairs are aggregated.
+  pub(super) fn capture_frame_size(
+    &self,
+    r#in: Option<&str>,
+    r#out: Option<&str>,
+    ether_type: &str,
+    frame_length: u64,
+    frames: u64,
+  ) {
+    if let Some(frame_sizes) = &self.frame_sizes {
+      let (r#in, r#out) = match (r#in, r#out) {
+        (Some(r#in), Some(r#out)) if self.router_series.is_rare_pair(r#in, r#out) => {
+          (Some(OTHER_ROUTER), Some(OTHER_ROUTER))
+        }
+        routers => routers,
+      };
+      frame_sizes.observe(r#in, r#out, ether_type, frame_length, frames);
+    }
+  }
+
+  /// Creates the series of a newly loaded meta configuration, so they are exported before any
+  /// traffic is seen.
+  pub(super) fn preseed(&self, meta: &Meta) {
+    for agent in meta.get_agents() {
+      self.capture_pagent_drops(&agent.label, 0);
+
+      for port in agent.get_ports() {
+        self.capture_port_bytes(&agent.label, &port.name, "in", 0);
+        self.capture_port_bytes(&agent.label, &port.name, "out", 0);
+      }
+    }
+
+    self
+      .agent_liveness
+      .preseed(meta.get_agents().map(|agent| agent.label.as_str()));
+
+    self
+      .router_series
+      .preseed(&meta.router_labels(), &meta.ether_type_labels());
+  }
+
+  pub(super) fn capture_agent_datagram(&self, agent: &str) {
+    self.agent_liveness.capture_datagram(agent);
+  }
+
+  pub(super) fn capture_agent_sample_rate(&self, agent: &str, source_id: &str, sample_rate: u32) {
+    self
+      .agent_sample_rate
+      .with_label_values(&[agent, source_id])
+      .set(sample_rate as i64);
+  }
+
+  pub(super) fn capture_pagent_drops(&self, agent: &str, drops: u32) {
+    self
+      .agent_drops
+      .with_label_values(&[agent])
+      .inc_by(drops as u64);
+  }
+
+  pub(super) fn capture_port_bytes(&self, agent: &str, port: &str, direction: &str, bytes: u64) {
+    self
+      .port_bytes
+      .with_label_values(&[agent, port, direction])
+      .inc_by(bytes);
+  }
+
+  pub(super) fn capture_router_mac_violation(&self, router: &str, agent: &str, port: &str) {
+    self
+      .router_mac_violations
+      .with_label_values(&[router, agent, port])
+      .inc();
+  }
+
+  pub(super) fn capture_learned_neighbors(&self, ipv4: usize, ipv6: usize) {
+    self
+      .learned_neighbors
+      .with_label_values(&["ipv4"])
+      .set(ipv4 as i64);
+    self
+      .learned_neighbors
+      .with_label_values(&["ipv6"])
+      .set(ipv6 as i64);
+  }
+
+  /// Records the outcome of a meta reload, `hash` is only passed if it was successful.
+  pub(super) fn capture_meta_reload(&self, hash: Option<&str>) {
+    let hash = match hash {
+      Some(hash) => hash,
+      None => {
+        self.meta_reload_success.set(0);
+        return;
+      }
+    };
+
+    self.meta_reload_success.set(1);
+    self.meta_last_reload_timestamp.set(
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default(),
+    );
+    self.meta_config_info.reset();
+    self.meta_config_info.with_label_values(&[hash]).set(1);
+  }
+
+  /// Removes all series of routers, agents, ports and ether types, that are not part of the
+  /// given meta configuration anymore.
+  pub(super) fn remove_stale(&self, meta: &Meta) {
+    let routers = meta.router_labels();
+    let agents = meta
+      .get_agents()
+      .map(|agent| {
+        let ports = agent
+          .get_ports()
+          .map(|port| port.name.as_str())
+          .collect::<HashSet<_>>();
+        (agent.label.as_str(), ports)
+      })
+      .collect::<HashMap<_, _>>();
+
+    let is_router = |label: &str| label == OTHER_ROUTER || routers.contains(label);
+    let is_stale_router_series = |labels: &HashMap<&str, &str>| {
+      !is_router(labels["in"])
+        || !is_router(labels["out"])
+        || !meta.is_ether_type_label(labels["ether_type"])
+    };
+    remove_series(&self.router_bytes, is_stale_router_series);
+    remove_series(&self.router_packets, is_stale_router_series);
+    if let Some(frame_sizes) = &self.frame_sizes {
+      // the histogram is not necessarily partitioned by all of the router labels
+      frame_sizes.retain(|labels| {
+        labels.get("in").is_some_and(|label| !is_router(label))
+          || labels.get("out").is_some_and(|label| !is_router(label))
+          || labels
+            .get("ether_type")
+            .is_some_and(|label| !meta.is_ether_type_label(label))
+      });
+    }
+    self.router_series.retain(&routers);
+    remove_series(&self.agent_drops, |labels| {
+      !agents.contains_key(labels["agent"])
+    });
+    remove_series(&self.agent_sample_rate, |labels| {
+      !agents.contains_key(labels["agent"])
+    });
+    self
+      .agent_liveness
+      .retain(&agents.keys().copied().collect());
+    remove_series(&self.port_bytes, |labels| {
+      agents
+        .get(labels["agent"])
+        .is_none_or(|ports| !ports.contains(labels["port"]))
+    });
+    remove_series(&self.router_mac_violations, |labels| {
+      !routers.contains(labels["router"]) || !agents.contains_key(labels["agent"])
+    });
+    remove_series(&self.router_mtu_violations, |labels| {
+      !routers.contains(labels["router"])
+    });
+  }
+}
+
+fn remove_series<T: MetricVecBuilder>(
+  vec: &MetricVec<T>,
+  is_stale: impl Fn(&HashMap<&str, &str>) -> bool,
+) {
+  for family in vec.collect() {
+    for metric in family.get_metric() {
+      let labels = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.name(), label.value()))
+        .collect::<HashMap<_, _>>();
+
+      if is_stale(&labels) {
+        // the series has just been collected, so it exists
+        let _ = vec.remove(&labels);
+      }
+    }
+  }
+}
@@ -0,0 +1,362 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+
+pub(super) const OPENMETRICS_FORMAT: &str =
+  "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Creation times of counters, histograms and summaries, which the prometheus crate does not
+/// track. A series is assumed to be created right after the last scrape it was missing from, so
+/// the timestamp is never later than the actual creation.
+pub(super) struct CreatedTimestamps {
+  boot: f64,
+  last_scrape: Option<f64>,
+  series: HashMap<String, f64>,
+}
+
+impl CreatedTimestamps {
+  pub(super) fn new() -> Self {
+    Self {
+      boot: now(),
+      last_scrape: None,
+      series: HashMap::new(),
+    }
+  }
+}
+
+/// Encodes metric families in the OpenMetrics text format.
+pub(super) fn encode(families: &[MetricFamily], created: &mut CreatedTimestamps) -> String {
+  let scrape = now();
+  let created_since = created.last_scrape.unwrap_or(created.boot);
+  let mut previous = std::mem::take(&mut created.series);
+
+  let mut buf = String::new();
+  for family in families {
+    let name = match family.get_field_type() {
+      MetricType::COUNTER => family
+        .name()
+        .strip_suffix("_total")
+        .unwrap_or(family.name()),
+      _ => family.name(),
+    };
+    let metric_type = match family.get_field_type() {
+      MetricType::COUNTER => "counter",
+      MetricType::GAUGE => "gauge",
+      MetricType::HISTOGRAM => "histogram",
+      MetricType::SUMMARY => "summary",
+      MetricType::UNTYPED => "unknown",
+    };
+
+    if !family.help().is_empty() {
+      writeln!(buf, "# HELP {} {}", name, escape(family.help())).unwrap();
+    }
+    writeln!(buf, "# TYPE {} {}", name, metric_type).unwrap();
+
+    for metric in family.get_metric() {
+      let labels = metric.get_label();
+      match family.get_field_type() {
+        MetricType::COUNTER => {
+          sample(
+            &mut buf,
+            name,
+            "_total",
+            labels,
+            None,
+            metric.get_counter().value(),
+          );
+        }
+        MetricType::GAUGE => sample(&mut buf, name, "", labels, None, metric.get_gauge().value()),
+        MetricType::UNTYPED => sample(&mut buf, name, "", labels, None, metric.untyped.value()),
+        MetricType::HISTOGRAM => {
+          let histogram = metric.get_histogram();
+          let mut has_inf = false;
+          for bucket in histogram.get_bucket() {
+            has_inf |= bucket.upper_bound() == f64::INFINITY;
+            let upper_bound = fmt_float(bucket.upper_bound());
+            let count = bucket.cumulative_count() as f64;
+            sample(
+              &mut buf,
+              name,
+              "_bucket",
+              labels,
+              Some(("le", &upper_bound)),
+              count,
+            );
+          }
+          if !has_inf {
+            let count = histogram.get_sample_count() as f64;
+            sample(
+              &mut buf,
+              name,
+              "_bucket",
+              labels,
+              Some(("le", "+Inf")),
+              count,
+            );
+          }
+          sample(
+            &mut buf,
+            name,
+            "_count",
+            labels,
+            None,
+            histogram.get_sample_count() as f64,
+          );
+          sample(
+            &mut buf,
+            name,
+            "_sum",
+            labels,
+            None,
+            histogram.get_sample_sum(),
+          );
+        }
+        MetricType::SUMMARY => {
+          let summary = metric.get_summary();
+          for quantile in summary.get_quantile() {
+            let rank = fmt_float(quantile.quantile());
+            sample(
+              &mut buf,
+              name,
+              "",
+              labels,
+              Some(("quantile", &rank)),
+              quantile.value(),
+            );
+          }
+          sample(
+            &mut buf,
+            name,
+            "_count",
+            labels,
+            None,
+            summary.sample_count() as f64,
+          );
+          sample(&mut buf, name, "_sum", labels, None, summary.sample_sum());
+        }
+      }
+
+      if family.get_field_type() != MetricType::GAUGE
+        && family.get_field_type() != MetricType::UNTYPED
+      {
+        let key = series_key(name, metric);
+        let timestamp = previous.remove(&key).unwrap_or(created_since);
+        sample(&mut buf, name, "_created", labels, None, timestamp);
+        created.series.insert(key, timestamp);
+      }
+    }
+  }
+  buf.push_str("# EOF\n");
+
+  created.last_scrape = Some(scrape);
+  buf
+}
+
+fn sample(
+  buf: &mut String,
+  name: &str,
+  suffix: &str,
+  labels: &[LabelPair],
+  extra_label: Option<(&str, &str)>,
+  value: f64,
+) {
+  buf.push_str(name);
+  buf.push_str(suffix);
+
+  let mut separator = '{';
+  let labels = labels
+    .iter()
+    .map(|label| (label.name(), label.value()))
+    .chain(extra_label);
+  for (name, value) in labels {
+    write!(buf, "{}{}=\"{}\"", separator, name, escape(value)).unwrap();
+    separator = ',';
+  }
+  if separator == ',' {
+    buf.push('}');
+  }
+
+  writeln!(buf, " {}", fmt_float(value)).unwrap();
+}
+
+fn series_key(name: &str, metric: &Metric) -> String {
+  let mut key = name.to_string();
+  for label in metric.get_label() {
+    write!(key, "\u{0}{}\u{0}{}", label.name(), label.value()).unwrap();
+  }
+  key
+}
+
+fn escape(value: &str) -> Cow<'_, str> {
+  if !value.contains(['\\', '"', '\n']) {
+    return Cow::Borrowed(value);
+  }
+
+  Cow::Owned(
+    value
+      .replace('\\', "\\\\")
+      .replace('"', "\\\"")
+      .replace('\n', "\\n"),
+  )
+}
+
+fn fmt_float(value: f64) -> String {
+  if value == f64::INFINITY {
+    "+Inf".to_string()
+  } else if value == f64::NEG_INFINITY {
+    "-Inf".to_string()
+  } else if value.is_nan() {
+    "NaN".to_string()
+  } else {
+    format!("{:?}", value)
+  }
+}
+
+fn now() -> f64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs_f64())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use prometheus::{
+    CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts, IntCounter, Opts, Registry,
+  };
+
+  use super::*;
+
+  fn lines(encoded: &str) -> Vec<&str> {
+    encoded.lines().collect()
+  }
+
+  fn created(encoded: &str, series: &str) -> f64 {
+    encoded
+      .lines()
+      .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+      .unwrap()
+  }
+
+  #[test]
+  fn encodes_counters_with_total_and_created() {
+    let registry = Registry::new();
+    let counter = IntCounter::new("sflow_bytes_total", "bytes").unwrap();
+    counter.inc_by(3);
+    registry.register(Box::new(counter)).unwrap();
+
+    let mut timestamps = CreatedTimestamps::new();
+    let encoded = encode(&registry.gather(), &mut timestamps);
+    let lines = lines(&encoded);
+
+    assert_eq!(lines[0], "# HELP sflow_bytes bytes");
+    assert_eq!(lines[1], "# TYPE sflow_bytes counter");
+    assert_eq!(lines[2], "sflow_bytes_total 3.0");
+    assert!(lines[3].starts_with("sflow_bytes_created "));
+    assert_eq!(lines[4], "# EOF");
+    assert_eq!(lines.len(), 5);
+    assert_eq!(created(&encoded, "sflow_bytes_created"), timestamps.boot);
+  }
+
+  #[test]
+  fn keeps_created_timestamps_between_scrapes() {
+    let registry = Registry::new();
+    let counter = CounterVec::new(Opts::new("sflow_bytes", "bytes"), &["router"]).unwrap();
+    counter.with_label_values(&["1234"]).inc();
+    registry.register(Box::new(counter.clone())).unwrap();
+
+    let mut timestamps = CreatedTimestamps::new();
+    let first = encode(&registry.gather(), &mut timestamps);
+    let scrape = timestamps.last_scrape.unwrap();
+    counter.with_label_values(&["4321"]).inc();
+    let second = encode(&registry.gather(), &mut timestamps);
+
+    let series = "sflow_bytes_created{router=\"1234\"}";
+    assert_eq!(created(&first, series), created(&second, series));
+    // created after the last scrape it was missing from
+    assert_eq!(
+      created(&second, "sflow_bytes_created{router=\"4321\"}"),
+      scrape
+    );
+  }
+
+  #[test]
+  fn encodes_histograms_and_gauges() {
+    let registry = Registry::new();
+    let histogram = Histogram::with_opts(
+      HistogramOpts::new("sflow_frame_size_bytes", "frame sizes").buckets(vec![64.0, 1500.0]),
+    )
+    .unwrap();
+    histogram.observe(100.0);
+    let gauge = Gauge::new("sflow_routers", "routers").unwrap();
+    gauge.set(2.0);
+    registry.register(Box::new(histogram)).unwrap();
+    registry.register(Box::new(gauge)).unwrap();
+
+    let encoded = encode(&registry.gather(), &mut CreatedTimestamps::new());
+    let lines = lines(&encoded);
+
+    assert_eq!(lines[1], "# TYPE sflow_frame_size_bytes histogram");
+    assert_eq!(lines[2], "sflow_frame_size_bytes_bucket{le=\"64.0\"} 0.0");
+    assert_eq!(lines[3], "sflow_frame_size_bytes_bucket{le=\"1500.0\"} 1.0");
+    assert_eq!(lines[4], "sflow_frame_size_bytes_bucket{le=\"+Inf\"} 1.0");
+    assert_eq!(lines[5], "sflow_frame_size_bytes_count 1.0");
+    assert_eq!(lines[6], "sflow_frame_size_bytes_sum 100.0");
+    assert!(lines[7].starts_with("sflow_frame_size_bytes_created "));
+    // gauges have no creation time
+    assert_eq!(
+      lines[8..],
+      [
+        "# HELP sflow_routers routers",
+        "# TYPE sflow_routers gauge",
+        "sflow_routers 2.0",
+        "# EOF"
+      ]
+    );
+  }
+
+  #[test]
+  fn escapes_label_values_and_help() {
+    let registry = Registry::new();
+    let gauge = GaugeVec::new(
+      Opts::new("sflow_info", "a \"quoted\" \\ help\ntext"),
+      &["label"],
+    )
+    .unwrap();
+    gauge
+      .with_label_values(&["back\\slash \"quote\"\nnewline"])
+      .set(1.0);
+    registry.register(Box::new(gauge)).unwrap();
+
+    let encoded = encode(&registry.gather(), &mut CreatedTimestamps::new());
+    let lines = lines(&encoded);
+
+    assert_eq!(
+      lines[0],
+      "# HELP sflow_info a \\\"quoted\\\" \\\\ help\\ntext"
+    );
+    assert_eq!(
+      lines[2],
+      "sflow_info{label=\"back\\\\slash \\\"quote\\\"\\nnewline\"} 1.0"
+    );
+    assert_eq!(escape("plain"), Cow::Borrowed("plain"));
+  }
+
+  #[test]
+  fn ends_with_eof() {
+    let encoded = encode(&[], &mut CreatedTimestamps::new());
+    assert_eq!(encoded, "# EOF\n");
+  }
+
+  #[test]
+  fn formats_floats() {
+    assert_eq!(fmt_float(1.0), "1.0");
+    assert_eq!(fmt_float(0.25), "0.25");
+    assert_eq!(fmt_float(f64::INFINITY), "+Inf");
+    assert_eq!(fmt_float(f64::NEG_INFINITY), "-Inf");
+    assert_eq!(fmt_float(f64::NAN), "NaN");
+  }
+}
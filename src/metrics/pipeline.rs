@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use prometheus::{exponential_buckets, Histogram, HistogramOpts, IntCounter, IntCounterVec};
+use prometheus::{IntGaugeVec, Opts, Registry};
+
+/// Metrics about the exporter itself, to tell whether datagrams reached it and how they were
+/// processed.
+pub(crate) struct Pipeline {
+  datagrams: IntCounter,
+  datagram_bytes: IntCounter,
+  datagram_errors: IntCounter,
+  decode_duration: Histogram,
+  samples: IntCounterVec,
+  flow_records: IntCounterVec,
+  meta_misses: IntCounterVec,
+}
+
+/// Kinds of lookups in the meta configuration.
+#[derive(Clone, Copy)]
+pub(crate) enum MetaLookup {
+  Agent,
+  Router,
+  Port,
+}
+
+impl Pipeline {
+  pub(super) fn new(registry: &Registry) -> Self {
+    let datagrams =
+      IntCounter::new("sflow_exporter_datagrams_total", "received datagrams").unwrap();
+    let datagram_bytes = IntCounter::new(
+      "sflow_exporter_datagram_bytes_total",
+      "bytes of received datagrams",
+    )
+    .unwrap();
+    let datagram_errors = IntCounter::new(
+      "sflow_exporter_datagram_errors_total",
+      "datagrams that could not be decoded",
+    )
+    .unwrap();
+    let decode_duration = Histogram::with_opts(
+      HistogramOpts::new(
+        "sflow_exporter_decode_duration_seconds",
+        "time spent decoding a datagram",
+      )
+      .buckets(exponential_buckets(0.000_001, 4.0, 8).unwrap()),
+    )
+    .unwrap();
+    let samples = IntCounterVec::new(
+      Opts::new("sflow_exporter_samples_total", "decoded samples"),
+      &["type"],
+    )
+    .unwrap();
+    let flow_records = IntCounterVec::new(
+      Opts::new("sflow_exporter_flow_records_total", "decoded flow records"),
+      &["type", "format"],
+    )
+    .unwrap();
+    let meta_misses = IntCounterVec::new(
+      Opts::new(
+        "sflow_exporter_meta_misses_total",
+        "lookups that are not covered by the meta configuration",
+      ),
+      &["kind"],
+    )
+    .unwrap();
+    let build_info = IntGaugeVec::new(
+      Opts::new("sflow_exporter_build_info", "version of the exporter"),
+      &["version"],
+    )
+    .unwrap();
+    build_info
+      .with_label_values(&[env!("CARGO_PKG_VERSION")])
+      .set(1);
+
+    for sample_type in ["flow", "counter", "unknown"] {
+      samples.with_label_values(&[sample_type]);
+    }
+    for kind in [MetaLookup::Agent, MetaLookup::Router, MetaLookup::Port] {
+      meta_misses.with_label_values(&[kind.label()]);
+    }
+
+    registry.register(Box::new(datagrams.clone())).unwrap();
+    registry.register(Box::new(datagram_bytes.clone())).unwrap();
+    registry
+      .register(Box::new(datagram_errors.clone()))
+      .unwrap();
+    registry
+      .register(Box::new(decode_duration.clone()))
+      .unwrap();
+    registry.register(Box::new(samples.clone())).unwrap();
+    registry.register(Box::new(flow_records.clone())).unwrap();
+    registry.register(Box::new(meta_misses.clone())).unwrap();
+    registry.register(Box::new(build_info)).unwrap();
+
+    Self {
+      datagrams,
+      datagram_bytes,
+      datagram_errors,
+      decode_duration,
+      samples,
+      flow_records,
+      meta_misses,
+    }
+  }
+
+  /// Records a received datagram, `decoded` is false if it could not be decoded.
+  pub(crate) fn capture_datagram(&self, bytes: usize, decode_duration: Duration, decoded: bool) {
+    self.datagrams.inc();
+    self.datagram_bytes.inc_by(bytes as u64);
+    self.decode_duration.observe(decode_duration.as_secs_f64());
+    if !decoded {
+      self.datagram_errors.inc();
+    }
+  }
+
+  pub(crate) fn capture_sample(&self, sample_type: &str) {
+    self.samples.with_label_values(&[sample_type]).inc();
+  }
+
+  pub(crate) fn capture_flow_record(&self, record_type: &str, format: &str) {
+    self
+      .flow_records
+      .with_label_values(&[record_type, format])
+      .inc();
+  }
+
+  pub(crate) fn capture_meta_miss(&self, kind: MetaLookup) {
+    self.meta_misses.with_label_values(&[kind.label()]).inc();
+  }
+}
+
+impl MetaLookup {
+  fn label(&self) -> &'static str {
+    match self {
+      MetaLookup::Agent => "agent",
+      MetaLookup::Router => "router",
+      MetaLookup::Port => "port",
+    }
+  }
+}
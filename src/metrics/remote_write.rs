@@ -0,0 +1,486 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, USER_AGENT};
+use reqwest::{StatusCode, Url};
+use tokio::time::MissedTickBehavior;
+use tracing::{error, warn};
+
+use crate::metrics::exposition::Exposition;
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+pub(crate) struct RemoteWriteOptions {
+  pub(crate) url: Url,
+  pub(crate) interval: Duration,
+  pub(crate) timeout: Duration,
+  pub(crate) auth: Option<RemoteWriteAuth>,
+  /// Retries of a failed push, before its samples are dropped.
+  pub(crate) max_retries: u32,
+}
+
+pub(crate) enum RemoteWriteAuth {
+  Basic {
+    username: String,
+    password: Option<String>,
+  },
+  Bearer(String),
+}
+
+enum PushError {
+  /// Network errors, server errors and rate limits, which may succeed on retry.
+  Recoverable(String),
+  Fatal(String),
+}
+
+impl fmt::Display for PushError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PushError::Recoverable(message) | PushError::Fatal(message) => f.write_str(message),
+    }
+  }
+}
+
+/// Periodically pushes the registry to a Prometheus remote write endpoint, if configured.
+pub(crate) async fn push_metrics(
+  exposition: Exposition,
+  options: Option<RemoteWriteOptions>,
+) -> anyhow::Result<()> {
+  let options = match options {
+    Some(options) => options,
+    None => return std::future::pending().await,
+  };
+
+  let client = reqwest::Client::builder()
+    .timeout(options.timeout)
+    .build()?;
+
+  let mut interval = tokio::time::interval(options.interval);
+  interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+  loop {
+    interval.tick().await;
+
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_millis() as i64)
+      .unwrap_or_default();
+    let body =
+      snap::raw::Encoder::new().compress_vec(&write_request(&exposition.gather(), timestamp))?;
+
+    if let Err(err) = push_with_retries(&client, &options, body).await {
+      error!("Failed to push metrics, dropping samples: {}", err);
+    }
+  }
+}
+
+/// Pushes a request, retrying recoverable errors with exponential backoff.
+async fn push_with_retries(
+  client: &reqwest::Client,
+  options: &RemoteWriteOptions,
+  body: Vec<u8>,
+) -> Result<(), PushError> {
+  let mut backoff = MIN_BACKOFF;
+  let mut retries = 0;
+  loop {
+    match push(client, options, body.clone()).await {
+      Err(PushError::Recoverable(err)) if retries < options.max_retries => {
+        warn!("Failed to push metrics, retrying in {:?}: {}", backoff, err);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        retries += 1;
+      }
+      result => return result,
+    }
+  }
+}
+
+async fn push(
+  client: &reqwest::Client,
+  options: &RemoteWriteOptions,
+  body: Vec<u8>,
+) -> Result<(), PushError> {
+  let mut request = client
+    .post(options.url.clone())
+    .header(CONTENT_ENCODING, "snappy")
+    .header(CONTENT_TYPE, "application/x-protobuf")
+    .header(
+      USER_AGENT,
+      concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+    )
+    .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+    .body(body);
+
+  request = match &options.auth {
+    Some(RemoteWriteAuth::Basic { username, password }) => {
+      request.basic_auth(username, password.as_ref())
+    }
+    Some(RemoteWriteAuth::Bearer(token)) => request.bearer_auth(token),
+    None => request,
+  };
+
+  let response = request
+    .send()
+    .await
+    .map_err(|err| PushError::Recoverable(err.to_string()))?;
+
+  let status = response.status();
+  if status.is_success() {
+    return Ok(());
+  }
+
+  let message = format!(
+    "{} {}",
+    status,
+    response.text().await.unwrap_or_default().trim()
+  );
+  Err(push_error(status, message))
+}
+
+/// Only server errors and rate limits are retried, other client errors would fail again.
+fn push_error(status: StatusCode, message: String) -> PushError {
+  if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+    PushError::Recoverable(message)
+  } else {
+    PushError::Fatal(message)
+  }
+}
+
+/// Encodes metric families as a `prometheus.WriteRequest` protobuf message, series are named
+/// like in the text format.
+fn write_request(families: &[MetricFamily], timestamp: i64) -> Vec<u8> {
+  let mut request = Vec::new();
+  let mut series = |name: &str, labels: &[LabelPair], extra: Option<(&str, &str)>, value: f64| {
+    let mut pairs = labels
+      .iter()
+      .map(|label| (label.name(), label.value()))
+      .chain(extra)
+      .chain([("__name__", name)])
+      .collect::<Vec<_>>();
+    pairs.sort_unstable_by_key(|(name, _)| *name);
+
+    let mut time_series = Vec::new();
+    for (name, value) in pairs {
+      let mut label = Vec::new();
+      put_bytes(&mut label, 1, name.as_bytes());
+      put_bytes(&mut label, 2, value.as_bytes());
+      put_bytes(&mut time_series, 1, &label);
+    }
+    let mut sample = Vec::new();
+    put_key(&mut sample, 1, WIRE_TYPE_I64);
+    sample.extend_from_slice(&value.to_le_bytes());
+    put_key(&mut sample, 2, WIRE_TYPE_VARINT);
+    put_varint(&mut sample, timestamp as u64);
+    put_bytes(&mut time_series, 2, &sample);
+
+    put_bytes(&mut request, 1, &time_series);
+  };
+
+  for family in families {
+    let name = family.name();
+    for metric in family.get_metric() {
+      let labels = metric.get_label();
+      match family.get_field_type() {
+        MetricType::COUNTER => series(name, labels, None, metric.get_counter().value()),
+        MetricType::GAUGE => series(name, labels, None, metric.get_gauge().value()),
+        MetricType::UNTYPED => series(name, labels, None, metric.untyped.value()),
+        MetricType::HISTOGRAM => {
+          let histogram = metric.get_histogram();
+          let bucket_name = format!("{}_bucket", name);
+          let mut has_inf = false;
+          for bucket in histogram.get_bucket() {
+            has_inf |= bucket.upper_bound() == f64::INFINITY;
+            let upper_bound = fmt_bound(bucket.upper_bound());
+            let count = bucket.cumulative_count() as f64;
+            series(&bucket_name, labels, Some(("le", &upper_bound)), count);
+          }
+          if !has_inf {
+            let count = histogram.get_sample_count() as f64;
+            series(&bucket_name, labels, Some(("le", "+Inf")), count);
+          }
+          let count = histogram.get_sample_count() as f64;
+          series(&format!("{}_count", name), labels, None, count);
+          series(
+            &format!("{}_sum", name),
+            labels,
+            None,
+            histogram.get_sample_sum(),
+          );
+        }
+        MetricType::SUMMARY => {
+          let summary = metric.get_summary();
+          for quantile in summary.get_quantile() {
+            let rank = fmt_bound(quantile.quantile());
+            series(name, labels, Some(("quantile", &rank)), quantile.value());
+          }
+          let count = summary.sample_count() as f64;
+          series(&format!("{}_count", name), labels, None, count);
+          series(&format!("{}_sum", name), labels, None, summary.sample_sum());
+        }
+      }
+    }
+  }
+
+  request
+}
+
+const WIRE_TYPE_VARINT: u8 = 0;
+const WIRE_TYPE_I64: u8 = 1;
+const WIRE_TYPE_LEN: u8 = 2;
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    buf.push(value as u8 | 0x80);
+    value >>= 7;
+  }
+  buf.push(value as u8);
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u8, wire_type: u8) {
+  buf.push(field << 3 | wire_type);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+  put_key(buf, field, WIRE_TYPE_LEN);
+  put_varint(buf, bytes.len() as u64);
+  buf.extend_from_slice(bytes);
+}
+
+fn fmt_bound(value: f64) -> String {
+  if value == f64::INFINITY {
+    "+Inf".to_string()
+  } else {
+    value.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  use axum::extract::State;
+  use axum::routing::post;
+  use axum::Router;
+  use prometheus::{GaugeVec, Histogram, HistogramOpts, Opts, Registry};
+  use tokio::net::TcpListener;
+
+  use super::*;
+
+  type Series = (Vec<(String, String)>, f64, i64);
+
+  fn read_varint(buf: &mut &[u8]) -> u64 {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+      let byte = buf[0];
+      *buf = &buf[1..];
+      value |= ((byte & 0x7f) as u64) << shift;
+      if byte < 0x80 {
+        break;
+      }
+    }
+    value
+  }
+
+  /// Reads the fields of a message, only the wire types written by `write_request` are supported.
+  fn read_fields(mut buf: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+      let key = read_varint(&mut buf);
+      let len = match key as u8 & 0x7 {
+        WIRE_TYPE_VARINT => {
+          let start = buf;
+          read_varint(&mut buf);
+          fields.push((key >> 3, &start[..start.len() - buf.len()]));
+          continue;
+        }
+        WIRE_TYPE_I64 => 8,
+        WIRE_TYPE_LEN => read_varint(&mut buf) as usize,
+        wire_type => panic!("unexpected wire type {}", wire_type),
+      };
+      fields.push((key >> 3, &buf[..len]));
+      buf = &buf[len..];
+    }
+    fields
+  }
+
+  fn decode(request: &[u8]) -> Vec<Series> {
+    read_fields(request)
+      .into_iter()
+      .map(|(field, time_series)| {
+        assert_eq!(field, 1);
+        let mut series = (Vec::new(), 0.0, 0);
+        for (field, value) in read_fields(time_series) {
+          let fields = read_fields(value);
+          match field {
+            1 => series.0.push((
+              String::from_utf8(fields[0].1.to_vec()).unwrap(),
+              String::from_utf8(fields[1].1.to_vec()).unwrap(),
+            )),
+            2 => {
+              series.1 = f64::from_le_bytes(fields[0].1.try_into().unwrap());
+              series.2 = read_varint(&mut { fields[1].1 }) as i64;
+            }
+            field => panic!("unexpected field {}", field),
+          }
+        }
+        series
+      })
+      .collect()
+  }
+
+  fn labels(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn encodes_write_requests() {
+    let registry = Registry::new();
+    let gauge = GaugeVec::new(Opts::new("up", "up"), &["job"]).unwrap();
+    gauge.with_label_values(&["a"]).set(1.0);
+    registry.register(Box::new(gauge)).unwrap();
+
+    let request = write_request(&registry.gather(), 1000);
+
+    #[rustfmt::skip]
+    let expected = [
+      0x0a, 40, // timeseries
+        0x0a, 14, // labels
+          0x0a, 8, b'_', b'_', b'n', b'a', b'm', b'e', b'_', b'_',
+          0x12, 2, b'u', b'p',
+        0x0a, 8, // labels
+          0x0a, 3, b'j', b'o', b'b',
+          0x12, 1, b'a',
+        0x12, 12, // samples
+          0x09, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f, // 1.0
+          0x10, 0xe8, 0x07, // 1000
+    ];
+    assert_eq!(request, expected);
+  }
+
+  #[test]
+  fn sorts_labels_by_name() {
+    let registry = Registry::new();
+    let gauge = GaugeVec::new(Opts::new("sflow_bytes", "bytes"), &["router", "agent"]).unwrap();
+    gauge.with_label_values(&["1234", "sw01"]).set(2.5);
+    registry.register(Box::new(gauge)).unwrap();
+
+    let series = decode(&write_request(&registry.gather(), 1));
+    assert_eq!(
+      series,
+      [(
+        labels(&[
+          ("__name__", "sflow_bytes"),
+          ("agent", "sw01"),
+          ("router", "1234")
+        ]),
+        2.5,
+        1
+      )]
+    );
+  }
+
+  #[test]
+  fn encodes_histograms_as_series() {
+    let registry = Registry::new();
+    let histogram =
+      Histogram::with_opts(HistogramOpts::new("sflow_size", "size").buckets(vec![64.0])).unwrap();
+    histogram.observe(100.0);
+    registry.register(Box::new(histogram)).unwrap();
+
+    let series = decode(&write_request(&registry.gather(), 1));
+    assert_eq!(
+      series,
+      [
+        (
+          labels(&[("__name__", "sflow_size_bucket"), ("le", "64")]),
+          0.0,
+          1
+        ),
+        (
+          labels(&[("__name__", "sflow_size_bucket"), ("le", "+Inf")]),
+          1.0,
+          1
+        ),
+        (labels(&[("__name__", "sflow_size_count")]), 1.0, 1),
+        (labels(&[("__name__", "sflow_size_sum")]), 100.0, 1),
+      ]
+    );
+  }
+
+  #[test]
+  fn retries_server_errors_and_rate_limits_only() {
+    for status in [
+      StatusCode::INTERNAL_SERVER_ERROR,
+      StatusCode::SERVICE_UNAVAILABLE,
+      StatusCode::TOO_MANY_REQUESTS,
+    ] {
+      assert!(
+        matches!(push_error(status, String::new()), PushError::Recoverable(_)),
+        "{}",
+        status
+      );
+    }
+    for status in [
+      StatusCode::BAD_REQUEST,
+      StatusCode::UNAUTHORIZED,
+      StatusCode::NOT_FOUND,
+      StatusCode::PAYLOAD_TOO_LARGE,
+    ] {
+      assert!(
+        matches!(push_error(status, String::new()), PushError::Fatal(_)),
+        "{}",
+        status
+      );
+    }
+  }
+
+  async fn pushes(status: StatusCode, max_retries: u32) -> (Result<(), PushError>, usize) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let router = Router::new()
+      .route(
+        "/write",
+        post(move |State(requests): State<Arc<AtomicUsize>>| async move {
+          requests.fetch_add(1, Ordering::Relaxed);
+          status
+        }),
+      )
+      .with_state(requests.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/write", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let options = RemoteWriteOptions {
+      url: url.parse().unwrap(),
+      interval: Duration::from_secs(30),
+      timeout: Duration::from_secs(5),
+      auth: None,
+      max_retries,
+    };
+    let result = push_with_retries(&reqwest::Client::new(), &options, Vec::new()).await;
+    (result, requests.load(Ordering::Relaxed))
+  }
+
+  #[tokio::test]
+  async fn does_not_retry_client_errors() {
+    let (result, requests) = pushes(StatusCode::BAD_REQUEST, 3).await;
+    assert!(matches!(result, Err(PushError::Fatal(_))));
+    assert_eq!(requests, 1);
+  }
+
+  #[tokio::test]
+  async fn retries_server_errors() {
+    let (result, requests) = pushes(StatusCode::SERVICE_UNAVAILABLE, 1).await;
+    assert!(matches!(result, Err(PushError::Recoverable(_))));
+    assert_eq!(requests, 2);
+
+    let (result, requests) = pushes(StatusCode::NO_CONTENT, 1).await;
+    assert!(result.is_ok());
+    assert_eq!(requests, 1);
+  }
+}
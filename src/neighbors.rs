@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::Json;
+use serde::Serialize;
+
+use crate::sflow::record::Untagged;
+use crate::utils::fmt_mac;
+
+const MAX_NEIGHBORS: usize = 65536;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_ARP: u16 = 0x0806;
+const ETHER_TYPE_IPV6: u16 = 0x86dd;
+
+const IP_PROTOCOL_ICMPV6: u8 = 58;
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const NDP_OPTION_SOURCE_LINK_LAYER_ADDR: u8 = 1;
+const NDP_OPTION_TARGET_LINK_LAYER_ADDR: u8 = 2;
+
+/// IP to mac bindings learned from sampled ARP and neighbor discovery packets, shared between the
+/// sflow processor and the http api.
+#[derive(Clone, Default)]
+pub(super) struct Neighbors {
+  table: Arc<Mutex<NeighborTable>>,
+}
+
+#[derive(Default)]
+struct NeighborTable {
+  addrs: HashMap<IpAddr, Neighbor>,
+  macs: HashMap<[u8; 6], Vec<IpAddr>>,
+  /// Addresses ordered by when they were last seen, the oldest is evicted first.
+  by_age: BTreeMap<u64, IpAddr>,
+  next_seq: u64,
+  ipv4_count: usize,
+}
+
+struct Neighbor {
+  mac: [u8; 6],
+  agent: String,
+  last_seen: u64,
+  seq: u64,
+}
+
+#[derive(Serialize)]
+pub(super) struct NeighborEntry {
+  pub(super) addr: IpAddr,
+  pub(super) mac: String,
+  pub(super) agent: String,
+  pub(super) last_seen: u64,
+}
+
+impl Neighbors {
+  /// Records the binding of an address, returns the number of learned ipv4 and ipv6 neighbors.
+  pub(super) fn learn(&self, addr: IpAddr, mac: [u8; 6], agent: &str) -> (usize, usize) {
+    let mut table = self.table.lock().unwrap();
+
+    let seq = table.next_seq;
+    table.next_seq += 1;
+    table.by_age.insert(seq, addr);
+
+    let previous = table.addrs.insert(
+      addr,
+      Neighbor {
+        mac,
+        agent: agent.to_string(),
+        last_seen: SystemTime::now()
+          .duration_since(UNIX_EPOCH)
+          .map(|duration| duration.as_secs())
+          .unwrap_or_default(),
+        seq,
+      },
+    );
+    if let Some(previous) = &previous {
+      table.by_age.remove(&previous.seq);
+    }
+
+    match previous {
+      Some(previous) if previous.mac == mac => {}
+      Some(previous) => {
+        table.unbind(&previous.mac, &addr);
+        table.macs.entry(mac).or_default().push(addr);
+      }
+      None => {
+        table.macs.entry(mac).or_default().push(addr);
+        if addr.is_ipv4() {
+          table.ipv4_count += 1;
+        }
+        if table.addrs.len() > MAX_NEIGHBORS {
+          table.evict_oldest();
+        }
+      }
+    }
+
+    (table.ipv4_count, table.addrs.len() - table.ipv4_count)
+  }
+
+  /// Returns the first result of `f` for the addresses learned for a mac address.
+  pub(super) fn find_map<T>(
+    &self,
+    mac: &[u8; 6],
+    f: impl FnMut(&IpAddr) -> Option<T>,
+  ) -> Option<T> {
+    self
+      .table
+      .lock()
+      .unwrap()
+      .macs
+      .get(mac)
+      .and_then(|addrs| addrs.iter().find_map(f))
+  }
+}
+
+impl NeighborTable {
+  fn unbind(&mut self, mac: &[u8; 6], addr: &IpAddr) {
+    if let Some(addrs) = self.macs.get_mut(mac) {
+      addrs.retain(|bound| bound != addr);
+      if addrs.is_empty() {
+        self.macs.remove(mac);
+      }
+    }
+  }
+
+  fn evict_oldest(&mut self) {
+    let addr = match self.by_age.pop_first() {
+      Some((_, addr)) => addr,
+      None => return,
+    };
+
+    if let Some(neighbor) = self.addrs.remove(&addr) {
+      self.unbind(&neighbor.mac, &addr);
+      if addr.is_ipv4() {
+        self.ipv4_count -= 1;
+      }
+    }
+  }
+}
+
+/// Extracts the binding announced by an ARP packet or an ICMPv6 neighbor solicitation or
+/// advertisement.
+pub(super) fn parse_binding(frame: &Untagged) -> Option<(IpAddr, [u8; 6])> {
+  match frame.ether_type {
+    ETHER_TYPE_ARP => parse_arp(frame.payload),
+    ETHER_TYPE_IPV6 => parse_neighbor_discovery(frame.payload),
+    _ => None,
+  }
+}
+
+fn parse_arp(arp: &[u8]) -> Option<(IpAddr, [u8; 6])> {
+  // ethernet hardware addresses and ipv4 protocol addresses only
+  if arp.get(0..6)?
+    != [
+      0,
+      1,
+      (ETHER_TYPE_IPV4 >> 8) as u8,
+      ETHER_TYPE_IPV4 as u8,
+      6,
+      4,
+    ]
+  {
+    return None;
+  }
+
+  let mac = arp.get(8..14)?.try_into().ok()?;
+  let addr = Ipv4Addr::from(<[u8; 4]>::try_from(arp.get(14..18)?).ok()?);
+
+  // probes announce no sender address
+  if addr.is_unspecified() {
+    return None;
+  }
+
+  Some((IpAddr::V4(addr), mac))
+}
+
+fn parse_neighbor_discovery(ipv6: &[u8]) -> Option<(IpAddr, [u8; 6])> {
+  // extension headers are not followed
+  if *ipv6.get(6)? != IP_PROTOCOL_ICMPV6 {
+    return None;
+  }
+
+  let src = Ipv6Addr::from(<[u8; 16]>::try_from(ipv6.get(8..24)?).ok()?);
+  let icmp = ipv6.get(40..)?;
+  let target = Ipv6Addr::from(<[u8; 16]>::try_from(icmp.get(8..24)?).ok()?);
+
+  // solicitations carry the link-layer address of the sender, advertisements the one of the target
+  let (addr, option) = match *icmp.first()? {
+    ICMPV6_NEIGHBOR_SOLICITATION if !src.is_unspecified() => {
+      (src, NDP_OPTION_SOURCE_LINK_LAYER_ADDR)
+    }
+    ICMPV6_NEIGHBOR_ADVERTISEMENT => (target, NDP_OPTION_TARGET_LINK_LAYER_ADDR),
+    _ => return None,
+  };
+
+  let mut options = icmp.get(24..)?;
+  while options.len() >= 8 {
+    let len = options[1] as usize * 8;
+    if len == 0 {
+      return None;
+    }
+    if options[0] == option {
+      return Some((IpAddr::V6(addr), options.get(2..8)?.try_into().ok()?));
+    }
+    options = options.get(len..)?;
+  }
+
+  None
+}
+
+pub(super) async fn neighbors_endpoint(
+  State(neighbors): State<Neighbors>,
+) -> Json<Vec<NeighborEntry>> {
+  let table = neighbors.table.lock().unwrap();
+  let mut entries = table
+    .addrs
+    .iter()
+    .map(|(addr, neighbor)| NeighborEntry {
+      addr: *addr,
+      mac: fmt_mac(&neighbor.mac),
+      agent: neighbor.agent.clone(),
+      last_seen: neighbor.last_seen,
+    })
+    .collect::<Vec<_>>();
+  entries.sort_by_key(|entry| entry.addr);
+  Json(entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MAC: [u8; 6] = [0x00, 0x1c, 0x73, 0x00, 0x00, 0x01];
+  const IPV4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+  const IPV6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+  const IPV6_PEER: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+
+  fn binding(ether_type: u16, payload: &[u8]) -> Option<(IpAddr, [u8; 6])> {
+    parse_binding(&Untagged {
+      vlan: None,
+      header_length: 14,
+      ether_type,
+      payload,
+    })
+  }
+
+  fn arp(operation: u16, sender: Ipv4Addr, target: Ipv4Addr) -> Vec<u8> {
+    let mut arp = vec![0, 1, 0x08, 0x00, 6, 4];
+    arp.extend_from_slice(&operation.to_be_bytes());
+    arp.extend_from_slice(&MAC);
+    arp.extend_from_slice(&sender.octets());
+    arp.extend_from_slice(&[0; 6]);
+    arp.extend_from_slice(&target.octets());
+    arp
+  }
+
+  fn neighbor_discovery(
+    icmp_type: u8,
+    src: Ipv6Addr,
+    target: Ipv6Addr,
+    options: &[(u8, &[u8])],
+  ) -> Vec<u8> {
+    let mut ipv6 = vec![0x60, 0, 0, 0, 0, 0, IP_PROTOCOL_ICMPV6, 255];
+    ipv6.extend_from_slice(&src.octets());
+    ipv6.extend_from_slice(&IPV6_PEER.octets());
+    ipv6.extend_from_slice(&[icmp_type, 0, 0, 0, 0, 0, 0, 0]);
+    ipv6.extend_from_slice(&target.octets());
+    for (option, value) in options {
+      ipv6.extend_from_slice(&[*option, (value.len() as u8 + 2).div_ceil(8)]);
+      ipv6.extend_from_slice(value);
+      ipv6.resize(ipv6.len().next_multiple_of(8), 0);
+    }
+    ipv6
+  }
+
+  #[test]
+  fn parses_arp() {
+    let request = arp(1, IPV4, Ipv4Addr::new(192, 0, 2, 2));
+    assert_eq!(
+      binding(ETHER_TYPE_ARP, &request),
+      Some((IpAddr::V4(IPV4), MAC))
+    );
+    // padded to the minimum frame size
+    let mut padded = request.clone();
+    padded.resize(46, 0);
+    assert_eq!(
+      binding(ETHER_TYPE_ARP, &padded),
+      Some((IpAddr::V4(IPV4), MAC))
+    );
+  }
+
+  #[test]
+  fn parses_gratuitous_arp() {
+    for operation in [1, 2] {
+      let gratuitous = arp(operation, IPV4, IPV4);
+      assert_eq!(
+        binding(ETHER_TYPE_ARP, &gratuitous),
+        Some((IpAddr::V4(IPV4), MAC))
+      );
+    }
+  }
+
+  #[test]
+  fn ignores_arp_probes_and_other_protocols() {
+    let probe = arp(1, Ipv4Addr::UNSPECIFIED, IPV4);
+    assert_eq!(binding(ETHER_TYPE_ARP, &probe), None);
+
+    let mut ipv6_arp = arp(1, IPV4, IPV4);
+    ipv6_arp[2..4].copy_from_slice(&ETHER_TYPE_IPV6.to_be_bytes());
+    assert_eq!(binding(ETHER_TYPE_ARP, &ipv6_arp), None);
+
+    assert_eq!(binding(ETHER_TYPE_IPV4, &arp(1, IPV4, IPV4)), None);
+  }
+
+  #[test]
+  fn ignores_truncated_arp() {
+    let request = arp(1, IPV4, IPV4);
+    for len in [0, 5, 13, 17] {
+      assert_eq!(binding(ETHER_TYPE_ARP, &request[..len]), None, "{}", len);
+    }
+  }
+
+  #[test]
+  fn parses_neighbor_solicitation() {
+    let solicitation = neighbor_discovery(
+      ICMPV6_NEIGHBOR_SOLICITATION,
+      IPV6,
+      IPV6_PEER,
+      &[(NDP_OPTION_SOURCE_LINK_LAYER_ADDR, &MAC)],
+    );
+    assert_eq!(
+      binding(ETHER_TYPE_IPV6, &solicitation),
+      Some((IpAddr::V6(IPV6), MAC))
+    );
+  }
+
+  #[test]
+  fn parses_neighbor_advertisement() {
+    // the target link-layer address may follow other options
+    let advertisement = neighbor_discovery(
+      ICMPV6_NEIGHBOR_ADVERTISEMENT,
+      IPV6_PEER,
+      IPV6,
+      &[(14, &[0; 6]), (NDP_OPTION_TARGET_LINK_LAYER_ADDR, &MAC)],
+    );
+    assert_eq!(
+      binding(ETHER_TYPE_IPV6, &advertisement),
+      Some((IpAddr::V6(IPV6), MAC))
+    );
+  }
+
+  #[test]
+  fn ignores_neighbor_discovery_without_link_layer_addr() {
+    let solicitation = neighbor_discovery(ICMPV6_NEIGHBOR_SOLICITATION, IPV6, IPV6_PEER, &[]);
+    assert_eq!(binding(ETHER_TYPE_IPV6, &solicitation), None);
+
+    let advertisement = neighbor_discovery(ICMPV6_NEIGHBOR_ADVERTISEMENT, IPV6_PEER, IPV6, &[]);
+    assert_eq!(binding(ETHER_TYPE_IPV6, &advertisement), None);
+
+    // advertisements only announce the link-layer address of the target
+    let advertisement = neighbor_discovery(
+      ICMPV6_NEIGHBOR_ADVERTISEMENT,
+      IPV6_PEER,
+      IPV6,
+      &[(NDP_OPTION_SOURCE_LINK_LAYER_ADDR, &MAC)],
+    );
+    assert_eq!(binding(ETHER_TYPE_IPV6, &advertisement), None);
+
+    // duplicate address detection solicits from the unspecified address
+    let solicitation = neighbor_discovery(
+      ICMPV6_NEIGHBOR_SOLICITATION,
+      Ipv6Addr::UNSPECIFIED,
+      IPV6,
+      &[(NDP_OPTION_SOURCE_LINK_LAYER_ADDR, &MAC)],
+    );
+    assert_eq!(binding(ETHER_TYPE_IPV6, &solicitation), None);
+  }
+
+  #[test]
+  fn ignores_truncated_neighbor_discovery() {
+    let solicitation = neighbor_discovery(
+      ICMPV6_NEIGHBOR_SOLICITATION,
+      IPV6,
+      IPV6_PEER,
+      &[(NDP_OPTION_SOURCE_LINK_LAYER_ADDR, &MAC)],
+    );
+    for len in [0, 6, 23, 39, 40, 63, 64, 70] {
+      assert_eq!(
+        binding(ETHER_TYPE_IPV6, &solicitation[..len]),
+        None,
+        "{}",
+        len
+      );
+    }
+
+    // options without a length would never end
+    let mut zero_length = solicitation.clone();
+    zero_length[65] = 0;
+    assert_eq!(binding(ETHER_TYPE_IPV6, &zero_length), None);
+  }
+
+  #[test]
+  fn evicts_the_least_recently_seen_neighbor() {
+    let neighbors = Neighbors::default();
+    let addr = |i: u32| IpAddr::V4(Ipv4Addr::from(i));
+    let mac = |i: u32| {
+      let [a, b, c, d] = i.to_be_bytes();
+      [0, 0, a, b, c, d]
+    };
+
+    for i in 0..MAX_NEIGHBORS as u32 {
+      neighbors.learn(addr(i), mac(i), "agent");
+    }
+    // seen again, so the second one is the oldest
+    neighbors.learn(addr(0), mac(0), "agent");
+    let counts = neighbors.learn(addr(u32::MAX), mac(u32::MAX), "agent");
+
+    assert_eq!(counts, (MAX_NEIGHBORS, 0));
+    assert_eq!(
+      neighbors.find_map(&mac(0), |addr| Some(*addr)),
+      Some(addr(0))
+    );
+    assert_eq!(neighbors.find_map(&mac(1), |addr| Some(*addr)), None);
+    assert!(!neighbors.table.lock().unwrap().addrs.contains_key(&addr(1)));
+  }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use futures_util::stream::StreamExt;
+use inotify::{Inotify, WatchMask};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+
+use crate::meta::api::ApiChange;
+use crate::meta::ixf::IxfSource;
+use crate::meta::{is_meta_file, MetaSource};
+
+// kubernetes swaps this symlink to atomically update all files of a mounted ConfigMap
+const CONFIG_MAP_DATA: &str = "..data";
+
+/// Requests the meta configuration to be reloaded, optionally reporting back the outcome.
+#[derive(Default)]
+pub(super) struct Reload {
+  result_tx: Option<oneshot::Sender<Result<(), String>>>,
+  /// Change of the runtime api to apply with the reload.
+  pub(super) change: Option<ApiChange>,
+}
+
+impl Reload {
+  pub(super) fn respond(self, result: Result<(), String>) {
+    if let Some(result_tx) = self.result_tx {
+      // the requester may have given up waiting
+      let _ = result_tx.send(result);
+    }
+  }
+}
+
+pub(super) async fn watch_files(
+  paths: Vec<PathBuf>,
+  buffer_size: usize,
+  meta_update_tx: mpsc::Sender<Reload>,
+) -> anyhow::Result<()> {
+  let mut files = Vec::new();
+  let mut meta_folders = Vec::new();
+  for path in paths {
+    let path = std::path::absolute(path)?;
+    if path.is_dir() {
+      meta_folders.push(path);
+    } else {
+      files.push(path);
+    }
+  }
+
+  let mut folders = meta_folders.clone();
+  for file in &files {
+    match file.parent() {
+      Some(parent) => folders.push(parent.to_path_buf()),
+      None => anyhow::bail!(
+        "Failed to watch for changes of {}. Parent folder could not be deteminated.",
+        file.display()
+      ),
+    };
+  }
+
+  let inotify = Inotify::init()?;
+  let mut watches = HashMap::new();
+  for folder in folders {
+    let watch = inotify.watches().add(
+      &folder,
+      WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM | WatchMask::DELETE,
+    )?;
+    watches.insert(watch, folder);
+  }
+
+  let mut buf = vec![0; buffer_size];
+  let mut stream = inotify.into_event_stream(&mut buf)?;
+
+  while let Some(event) = stream.next().await.transpose()? {
+    let (name, folder) = match (event.name, watches.get(&event.wd)) {
+      (Some(name), Some(folder)) => (name, folder),
+      _ => continue,
+    };
+
+    let path = folder.join(&name);
+    let is_fragment = meta_folders.contains(folder) && is_meta_file(&path);
+    if !files.contains(&path) && !is_fragment && name != CONFIG_MAP_DATA {
+      continue;
+    }
+    meta_update_tx.send(Reload::default()).await?;
+  }
+
+  Ok(())
+}
+
+/// Fallback for file systems that do not emit inotify events, like some bind mounts.
+pub(super) async fn poll_files(
+  meta_source: MetaSource,
+  interval: Duration,
+  meta_update_tx: mpsc::Sender<Reload>,
+) -> anyhow::Result<()> {
+  if interval.is_zero() {
+    return std::future::pending().await;
+  }
+
+  let mut last_hash = meta_source.hash_files().await.ok();
+
+  loop {
+    tokio::time::sleep(interval).await;
+
+    let hash = match meta_source.hash_files().await {
+      Ok(hash) => hash,
+      Err(err) => {
+        error!("Unable to hash meta configuration: {:?}", err);
+        continue;
+      }
+    };
+
+    if last_hash.as_ref() != Some(&hash) {
+      last_hash = Some(hash);
+      meta_update_tx.send(Reload::default()).await?;
+    }
+  }
+}
+
+pub(super) async fn poll_ixf(
+  ixf: Option<IxfSource>,
+  interval: Duration,
+  meta_update_tx: mpsc::Sender<Reload>,
+) -> anyhow::Result<()> {
+  // member exports on the local file system are watched like meta files
+  let ixf = match ixf {
+    Some(ixf) if ixf.path().is_none() => ixf,
+    _ => return std::future::pending().await,
+  };
+
+  loop {
+    tokio::time::sleep(interval).await;
+
+    match ixf.refresh().await {
+      Ok(true) => meta_update_tx.send(Reload::default()).await?,
+      Ok(false) => {}
+      Err(err) => error!("Unable to fetch IX-F member export: {:?}", err),
+    }
+  }
+}
+
+pub(super) async fn watch_sighup(meta_update_tx: mpsc::Sender<Reload>) -> anyhow::Result<()> {
+  let mut hangup = signal(SignalKind::hangup())?;
+
+  while hangup.recv().await.is_some() {
+    info!("Received SIGHUP, reloading meta configuration...");
+    meta_update_tx.send(Reload::default()).await?;
+  }
+
+  Ok(())
+}
+
+/// Reloads the meta configuration and waits for the outcome. Returns `None` if the sflow
+/// processing is not running.
+pub(super) async fn request_reload(
+  meta_update_tx: &mpsc::Sender<Reload>,
+  change: Option<ApiChange>,
+) -> Option<Result<(), String>> {
+  let (result_tx, result_rx) = oneshot::channel();
+
+  let reload = Reload {
+    result_tx: Some(result_tx),
+    change,
+  };
+
+  meta_update_tx.send(reload).await.ok()?;
+  result_rx.await.ok()
+}
+
+pub(super) async fn reload_endpoint(
+  State(meta_update_tx): State<mpsc::Sender<Reload>>,
+) -> Result<&'static str, (StatusCode, String)> {
+  match request_reload(&meta_update_tx, None).await {
+    Some(Ok(())) => Ok("Meta configuration reloaded\n"),
+    Some(Err(err)) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{}\n", err))),
+    None => Err((
+      StatusCode::SERVICE_UNAVAILABLE,
+      "sflow processing is not running\n".to_string(),
+    )),
+  }
+}
@@ -3,7 +3,6 @@ use std::io::{Read, Seek};
 use std::net::{Ipv4Addr, Ipv6Addr};
 
 use binrw::{BinRead, BinResult, Endian};
-use serde::Deserialize;
 
 use crate::sflow::sample::Sample;
 use crate::sflow::IpAddr::{IPv4, IPv6};
@@ -11,6 +10,7 @@ use crate::sflow::IpAddr::{IPv4, IPv6};
 pub(crate) mod record;
 pub(crate) mod sample;
 
+#[allow(dead_code)]
 #[derive(BinRead)]
 pub(crate) struct SflowDatagram {
   #[brw(assert(version == 5))]
@@ -23,8 +23,8 @@ pub(crate) struct SflowDatagram {
   #[br(count = sample_count)]
   pub(crate) samples: Vec<Sample>,
 }
-#[derive(Deserialize, Eq, PartialEq, Hash)]
-#[serde(untagged)]
+
+#[derive(Eq, PartialEq, Hash)]
 pub(crate) enum IpAddr {
   IPv4(Ipv4Addr),
   IPv6(Ipv6Addr),
@@ -39,6 +39,15 @@ impl Display for IpAddr {
   }
 }
 
+impl From<&IpAddr> for std::net::IpAddr {
+  fn from(value: &IpAddr) -> Self {
+    match value {
+      IPv4(v4) => std::net::IpAddr::V4(*v4),
+      IPv6(v6) => std::net::IpAddr::V6(*v6),
+    }
+  }
+}
+
 impl BinRead for IpAddr {
   type Args<'a> = ();
 
@@ -2,7 +2,19 @@ use std::io::{Read, Seek, SeekFrom};
 use std::mem;
 
 use binrw::{BinRead, BinResult, Endian};
+use clap::ValueEnum;
+use serde::Deserialize;
 
+const ETHER_TYPE_VLAN: u16 = 0x8100;
+const ETHER_TYPE_QINQ: u16 = 0x88a8;
+const MAX_VLAN_TAGS: usize = 2;
+const VLAN_TAG_LENGTH: usize = 4;
+// preamble, start frame delimiter and inter-frame gap
+const L1_OVERHEAD: u64 = 7 + 1 + 12;
+const MIN_FRAME_LENGTH: u64 = 64;
+const FCS_LENGTH: u64 = 4;
+
+#[allow(dead_code)]
 #[derive(BinRead)]
 pub(crate) enum FlowRecord {
   #[brw(magic = 1u32)]
@@ -191,6 +203,7 @@ enum HeaderProtocolRaw {
   // },
 }
 
+#[allow(dead_code)]
 pub(crate) enum HeaderProtocol {
   Ethernet(EthernetHeader),
   // ISO88024TokenBus,
@@ -211,6 +224,64 @@ pub(crate) enum HeaderProtocol {
   Unknown { magic: u32 },
 }
 
+impl FlowRecord {
+  pub(crate) fn kind(&self) -> &'static str {
+    match self {
+      FlowRecord::RawPacketHeader(_) => "raw_packet_header",
+      FlowRecord::Unknown { .. } => "unknown",
+    }
+  }
+
+  /// Data format of the record, named like in the sFlow specifications. Formats of other
+  /// enterprises and unassigned ones are `unknown`, as senders may pick any number.
+  pub(crate) fn format(&self) -> &'static str {
+    let magic = match self {
+      FlowRecord::RawPacketHeader(_) => return "sampled_header",
+      FlowRecord::Unknown { magic, .. } => *magic,
+    };
+
+    match magic {
+      1 => "sampled_header",
+      2 => "sampled_ethernet",
+      3 => "sampled_ipv4",
+      4 => "sampled_ipv6",
+      1001 => "extended_switch",
+      1002 => "extended_router",
+      1003 => "extended_gateway",
+      1004 => "extended_user",
+      1005 => "extended_url",
+      1006 => "extended_mpls",
+      1007 => "extended_nat",
+      1008 => "extended_mpls_tunnel",
+      1009 => "extended_mpls_vc",
+      1010 => "extended_mpls_ftn",
+      1011 => "extended_mpls_ldp_fec",
+      1012 => "extended_vlantunnel",
+      1013 => "extended_80211_payload",
+      1014 => "extended_80211_rx",
+      1015 => "extended_80211_tx",
+      1016 => "extended_80211_aggregation",
+      2100 => "extended_socket_ipv4",
+      2101 => "extended_socket_ipv6",
+      _ => "unknown",
+    }
+  }
+}
+
+/// Decides which octets of a frame are accounted as bytes.
+#[derive(Clone, Copy, Default, ValueEnum, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Accounting {
+  /// The frame without its FCS.
+  L2,
+  /// The frame including its FCS, as reported by the agent.
+  #[default]
+  #[value(name = "l2_fcs")]
+  L2Fcs,
+  /// The frame padded to the minimum frame size, including preamble and inter-frame gap.
+  L1,
+}
+
 pub(crate) struct RawPacketHeaderData {
   pub(crate) frame_length: u32,
   pub(crate) stripped_octets: u32,
@@ -227,10 +298,68 @@ struct RawPacketHeaderDataRaw {
 }
 
 #[derive(BinRead)]
+#[br(import(header_length: u32))]
 pub(crate) struct EthernetHeader {
   pub(crate) dst: [u8; 6],
   pub(crate) src: [u8; 6],
   pub(crate) ether_type: u16,
+  /// The sampled bytes following the ethernet header.
+  #[br(count = header_length.saturating_sub(2 * 6 + mem::size_of::<u16>() as u32))]
+  pub(crate) payload: Vec<u8>,
+}
+
+impl RawPacketHeaderData {
+  /// Length of the frame with respect to the accounting mode.
+  pub(crate) fn accounted_length(&self, accounting: Accounting) -> u64 {
+    let frame_length = self.frame_length as u64;
+    match accounting {
+      // the frame length includes the FCS, the stripped octets may include vlan tags as well
+      Accounting::L2 => frame_length.saturating_sub(FCS_LENGTH),
+      Accounting::L2Fcs => frame_length,
+      Accounting::L1 => frame_length.max(MIN_FRAME_LENGTH) + L1_OVERHEAD,
+    }
+  }
+}
+
+/// The ether type and payload enclosed by the vlan tags of a frame.
+pub(crate) struct Untagged<'a> {
+  /// Id of the outermost vlan.
+  pub(crate) vlan: Option<u16>,
+  /// Bytes of the ethernet header including all vlan tags.
+  pub(crate) header_length: u32,
+  pub(crate) ether_type: u16,
+  pub(crate) payload: &'a [u8],
+}
+
+impl EthernetHeader {
+  /// Strips up to two 802.1Q or 802.1ad tags. Tags cut off by the sampled header are kept.
+  pub(crate) fn untag(&self) -> Untagged<'_> {
+    let mut untagged = Untagged {
+      vlan: None,
+      header_length: (2 * 6 + mem::size_of::<u16>()) as u32,
+      ether_type: self.ether_type,
+      payload: &self.payload,
+    };
+
+    for _ in 0..MAX_VLAN_TAGS {
+      if untagged.ether_type != ETHER_TYPE_VLAN && untagged.ether_type != ETHER_TYPE_QINQ {
+        break;
+      }
+      let tag = match untagged.payload.get(..VLAN_TAG_LENGTH) {
+        Some(tag) => tag,
+        None => break,
+      };
+
+      untagged.vlan = untagged
+        .vlan
+        .or(Some(u16::from_be_bytes([tag[0], tag[1]]) & 0x0fff));
+      untagged.header_length += VLAN_TAG_LENGTH as u32;
+      untagged.ether_type = u16::from_be_bytes([tag[2], tag[3]]);
+      untagged.payload = &untagged.payload[VLAN_TAG_LENGTH..];
+    }
+
+    untagged
+  }
 }
 
 impl BinRead for RawPacketHeaderData {
@@ -241,19 +370,18 @@ impl BinRead for RawPacketHeaderData {
     endian: Endian,
     _args: Self::Args<'_>,
   ) -> BinResult<Self> {
+    let start = reader.stream_position()?;
     let raw: RawPacketHeaderDataRaw = RawPacketHeaderDataRaw::read_options(reader, endian, ())?;
-    let packet_header_size = mem::size_of::<u32>() * 4;
-
-    let mut header = raw.data_len - packet_header_size as u32;
+    let data_len_size = mem::size_of::<u32>() as u64;
 
     let protocol = match raw.protocol {
-      HeaderProtocolRaw::EthernetISO88023 => {
-        header -= 2 * 6 + mem::size_of::<u16>() as u32;
-        HeaderProtocol::Ethernet(EthernetHeader::read_options(reader, endian, ())?)
-      }
+      HeaderProtocolRaw::EthernetISO88023 => HeaderProtocol::Ethernet(
+        EthernetHeader::read_options(reader, endian, (raw.header_length,))?,
+      ),
     };
 
-    reader.seek(SeekFrom::Current(header as i64))?;
+    // the header is padded to a multiple of four bytes
+    reader.seek(SeekFrom::Start(start + data_len_size + raw.data_len as u64))?;
 
     Ok(RawPacketHeaderData {
       frame_length: raw.frame_length,
@@ -262,3 +390,34 @@ impl BinRead for RawPacketHeaderData {
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn packet_header(frame_length: u32, stripped_octets: u32) -> RawPacketHeaderData {
+    RawPacketHeaderData {
+      frame_length,
+      stripped_octets,
+      protocol_header: HeaderProtocol::Unknown { magic: 0 },
+    }
+  }
+
+  #[test]
+  fn accounts_frame_lengths() {
+    let header = packet_header(1518, 4);
+    assert_eq!(header.accounted_length(Accounting::L2Fcs), 1518);
+    assert_eq!(header.accounted_length(Accounting::L2), 1514);
+    assert_eq!(header.accounted_length(Accounting::L1), 1538);
+
+    // padded to the minimum frame size
+    assert_eq!(packet_header(60, 4).accounted_length(Accounting::L1), 84);
+  }
+
+  #[test]
+  fn only_subtracts_the_fcs() {
+    // a vlan tag stripped by the hardware in addition to the FCS
+    let header = packet_header(1522, 8);
+    assert_eq!(header.accounted_length(Accounting::L2), 1518);
+  }
+}
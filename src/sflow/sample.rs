@@ -19,8 +19,8 @@ pub(crate) enum Sample {
 
 pub(crate) struct FlowData {
   // pub(crate) seq_num: u32,
-  // pub(crate) source_id_idx: u32,
-  // pub(crate) source_id_type: u32,
+  pub(crate) source_id_idx: u32,
+  pub(crate) source_id_type: u32,
   pub(crate) sample_rate: u32,
   // pub(crate) sample_pool: u32,
   pub(crate) drops: u32,
@@ -32,6 +32,7 @@ pub(crate) struct FlowData {
   pub(crate) records: Vec<FlowRecord>,
 }
 
+#[allow(dead_code)]
 #[derive(BinRead)]
 struct FlowDataRaw {
   data_len: u32,
@@ -73,6 +74,7 @@ struct FlowDataRaw {
 //     data: Vec<u8>,
 // }
 
+#[allow(dead_code)]
 #[derive(BinRead)]
 pub(crate) struct UnknownData {
   pub(crate) magic: u32,
@@ -87,6 +89,17 @@ pub(crate) struct UnknownData {
 //   Unknown,
 // }
 
+impl Sample {
+  /// Type of the sample, counter samples are not decoded.
+  pub(crate) fn kind(&self) -> &'static str {
+    match self {
+      Sample::Flow(_) => "flow",
+      Sample::Unknown(UnknownData { magic: 2 | 4, .. }) => "counter",
+      Sample::Unknown(_) => "unknown",
+    }
+  }
+}
+
 impl BinRead for FlowData {
   type Args<'a> = ();
 
@@ -97,12 +110,12 @@ impl BinRead for FlowData {
   ) -> BinResult<Self> {
     let raw: FlowDataRaw = FlowDataRaw::read_options(reader, endian, ())?;
 
-    // let source_id_idx = raw.source_id & 0x00ffffff;
+    let source_id_idx = raw.source_id & 0x00ffffff;
 
     Ok(Self {
       // seq_num: raw.seq_num,
-      // source_id_idx,
-      // source_id_type: raw.source_id >> 24,
+      source_id_idx,
+      source_id_type: raw.source_id >> 24,
       sample_rate: raw.sample_rate,
       // sample_pool: raw.sample_pool,
       drops: raw.drops,
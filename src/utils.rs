@@ -1,4 +1,8 @@
 use std::mem;
+use std::net::SocketAddr;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
 
 use tokio::select;
 use tokio::signal::ctrl_c;
@@ -39,3 +43,41 @@ const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize - mem::size_of::<u16>();
 pub(super) fn datagram_buffer() -> Box<[u8; MAX_DATAGRAM_SIZE]> {
   Box::new([0u8; MAX_DATAGRAM_SIZE])
 }
+
+/// Binds a non-blocking udp socket, optionally resizing its kernel receive buffer to absorb
+/// bursts of datagrams.
+pub(super) fn bind_udp(
+  addr: SocketAddr,
+  receive_buffer: Option<usize>,
+) -> anyhow::Result<UdpSocket> {
+  let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+  if let Some(receive_buffer) = receive_buffer {
+    socket.set_recv_buffer_size(receive_buffer)?;
+  }
+  socket.set_nonblocking(true)?;
+  socket.bind(&addr.into())?;
+
+  Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Formats bytes with decimal units, like `1.2 GB`.
+pub(super) fn fmt_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+
+  let mut value = bytes as f64;
+  let mut unit = 0;
+  while value >= 1000.0 && unit < UNITS.len() - 1 {
+    value /= 1000.0;
+    unit += 1;
+  }
+
+  format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub(super) fn fmt_mac(mac: &[u8; 6]) -> String {
+  mac
+    .iter()
+    .map(|seg| format!("{:02x}", seg))
+    .collect::<Vec<String>>()
+    .join(":")
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::Json;
+use serde::Serialize;
+
+use crate::utils::fmt_mac;
+
+const MAX_VIOLATIONS: usize = 100;
+
+/// Keeps the most recent mac and mtu violations, shared between the sflow processor and the http
+/// api.
+#[derive(Clone, Default)]
+pub(super) struct Violations {
+  last: Arc<Mutex<VecDeque<Violation>>>,
+  last_mtu: Arc<Mutex<VecDeque<MtuViolation>>>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct Violation {
+  pub(super) timestamp: u64,
+  pub(super) router: String,
+  pub(super) mac: String,
+  pub(super) agent: String,
+  pub(super) port: String,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct MtuViolation {
+  pub(super) timestamp: u64,
+  pub(super) router: String,
+  pub(super) agent: String,
+  pub(super) port: String,
+  pub(super) vlan: Option<u16>,
+  /// Size of the packet enclosed by the frame.
+  pub(super) size: u32,
+  pub(super) mtu: u32,
+}
+
+impl Violations {
+  pub(super) fn record(&self, router: &str, mac: &[u8; 6], agent: &str, port: &str) {
+    let violation = Violation {
+      timestamp: now(),
+      router: router.to_string(),
+      mac: fmt_mac(mac),
+      agent: agent.to_string(),
+      port: port.to_string(),
+    };
+
+    push_bounded(&mut self.last.lock().unwrap(), violation);
+  }
+
+  pub(super) fn record_mtu(
+    &self,
+    router: &str,
+    agent: &str,
+    port: &str,
+    vlan: Option<u16>,
+    size: u32,
+    mtu: u32,
+  ) {
+    let violation = MtuViolation {
+      timestamp: now(),
+      router: router.to_string(),
+      agent: agent.to_string(),
+      port: port.to_string(),
+      vlan,
+      size,
+      mtu,
+    };
+
+    push_bounded(&mut self.last_mtu.lock().unwrap(), violation);
+  }
+}
+
+fn push_bounded<T>(last: &mut VecDeque<T>, violation: T) {
+  if last.len() == MAX_VIOLATIONS {
+    last.pop_front();
+  }
+  last.push_back(violation);
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or_default()
+}
+
+pub(super) async fn violations_endpoint(
+  State(violations): State<Violations>,
+) -> Json<Vec<Violation>> {
+  Json(
+    violations
+      .last
+      .lock()
+      .unwrap()
+      .iter()
+      .rev()
+      .cloned()
+      .collect(),
+  )
+}
+
+pub(super) async fn mtu_violations_endpoint(
+  State(violations): State<Violations>,
+) -> Json<Vec<MtuViolation>> {
+  Json(
+    violations
+      .last_mtu
+      .lock()
+      .unwrap()
+      .iter()
+      .rev()
+      .cloned()
+      .collect(),
+  )
+}